@@ -12,7 +12,7 @@ pub fn screen_to_world_dir(
     camera: &Camera,
 ) -> Line {
     let position = window.cursor_position().unwrap();
-    let screen_size = Vec2::from([window.width() as f32, window.height() as f32]);
+    let screen_size = Vec2::from([window.width(), window.height()]);
     let proj = camera.projection_matrix;
     let view = camera_transform.compute_matrix();
     // 2D Normalized device coordinate cursor position from (-1, -1) to (1, 1)
@@ -123,7 +123,7 @@ fn pan_orbit_camera(
             let yaw = Quat::from_rotation_y(-delta_x);
             let pitch = Quat::from_rotation_x(-delta_y);
             transform.rotation = yaw * transform.rotation; // rotate around global y axis
-            transform.rotation = transform.rotation * pitch; // rotate around local x axis
+            transform.rotation *= pitch; // rotate around local x axis
         } else if pan.length_squared() > 0.0 {
             any = true;
             // make panning distance independent of resolution and FOV,
@@ -155,18 +155,13 @@ fn pan_orbit_camera(
 
 fn get_primary_window_size(windows: &Res<Windows>) -> Vec2 {
     let window = windows.get_primary().unwrap();
-    let window = Vec2::new(window.width() as f32, window.height() as f32);
-    window
+    Vec2::new(window.width(), window.height())
 }
 
 /// Spawn a camera like this
-fn spawn_camera(mut commands: Commands, name: Local<String>) {
+fn spawn_camera(mut commands: Commands, _name: Local<String>) {
     let translation = Vec3::new(-2.0, 2.5, 5.0);
     let radius = translation.length();
-    let camera = Camera {
-        name: Some(name.clone()),
-        ..Default::default()
-    };
     commands
         .spawn_bundle(PerspectiveCameraBundle {
             transform: Transform::from_translation(translation).looking_at(Vec3::ZERO, Vec3::Y),
@@ -29,6 +29,15 @@ where
     }
 }
 
+impl<Layer> Default for RayLayerPlugin<Layer>
+where
+    Layer: Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<Layer> Plugin for RayLayerPlugin<Layer>
 where
     Layer: Send + Sync + 'static,
@@ -52,6 +61,15 @@ where
     }
 }
 
+impl<Layer> Default for RayHitable<Layer>
+where
+    Layer: Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct FireRay<Layer>
 where
     Layer: Send + Sync + 'static,
@@ -77,9 +95,12 @@ where
     Layer: Send + Sync + 'static,
 {
     pub entity: Entity,
+    /// Vertex indices of the triangle of the entity's mesh closest along the ray.
+    pub triangle: [usize; 3],
     pub(crate) _m: PhantomData<Layer>,
 }
 
+#[allow(clippy::type_complexity)]
 pub fn fire_ray<Layer: Send + Sync + 'static>(
     mut commands: Commands,
     mut materials: ResMut<Assets<StandardMaterial>>,
@@ -115,81 +136,12 @@ pub fn fire_ray<Layer: Send + Sync + 'static>(
                     error!("Cannot pick non Triangle list meshes!");
                     continue;
                 }
-                let pos = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
-                    Some(vertexs_pos) => match vertexs_pos {
-                        VertexAttributeValues::Float32x3(positions) => positions,
-                        _ => panic!("Mesh position type unexpected"),
-                    },
-                    None => panic!("Mesh doesn't have indices!"),
-                };
                 let world_to_mesh = gtrans.compute_matrix().inverse();
                 let ray_line = ray.line.transform(world_to_mesh);
-                let hit = if let Some(inds) = mesh.indices() {
-                    match inds {
-                        Indices::U16(inds) => {
-                            let mut hit = false;
-                            for index in inds.chunks(3) {
-                                let i1 = index[0] as usize;
-                                let i2 = index[2] as usize;
-                                let i3 = index[3] as usize;
-                                if ray_line
-                                    .intersect_tri(&[
-                                        Vec3A::new(pos[i1][0], pos[i1][1], pos[i1][2]),
-                                        Vec3A::new(pos[i2][0], pos[i2][1], pos[i2][2]),
-                                        Vec3A::new(pos[i3][0], pos[i3][1], pos[i3][2]),
-                                    ])
-                                    .is_some()
-                                {
-                                    hit = true;
-                                    break;
-                                }
-                            }
-                            hit
-                        }
-                        Indices::U32(inds) => {
-                            let mut hit = false;
-                            for index in inds.chunks(3) {
-                                let i1 = index[0] as usize;
-                                let i2 = index[2] as usize;
-                                let i3 = index[3] as usize;
-                                if ray_line
-                                    .intersect_tri(&[
-                                        Vec3A::new(pos[i1][0], pos[i1][1], pos[i1][2]),
-                                        Vec3A::new(pos[i2][0], pos[i2][1], pos[i2][2]),
-                                        Vec3A::new(pos[i3][0], pos[i3][1], pos[i3][2]),
-                                    ])
-                                    .is_some()
-                                {
-                                    hit = true;
-                                    break;
-                                }
-                            }
-                            hit
-                        }
-                    }
-                } else {
-                    let mut hit = false;
-                    for positions in pos.chunks(3) {
-                        let pos1 = positions[0];
-                        let pos2 = positions[1];
-                        let pos3 = positions[2];
-                        if ray_line
-                            .intersect_tri(&[
-                                Vec3A::new(pos1[0], pos1[1], pos1[2]),
-                                Vec3A::new(pos2[0], pos2[1], pos2[2]),
-                                Vec3A::new(pos3[0], pos3[1], pos3[2]),
-                            ])
-                            .is_some()
-                        {
-                            hit = true;
-                            break;
-                        }
-                    }
-                    hit
-                };
-                if hit {
+                if let Some((_, triangle)) = intersect_mesh(mesh, &ray_line) {
                     hits.send(RayHit {
                         entity,
+                        triangle,
                         _m: PhantomData,
                     })
                 }
@@ -198,6 +150,46 @@ pub fn fire_ray<Layer: Send + Sync + 'static>(
     }
 }
 
+/// Closest triangle of a triangle list `mesh` crossed by `ray`, given in mesh
+/// space, along with the parameter of the crossing along the ray.
+pub fn intersect_mesh(mesh: &Mesh, ray: &Line) -> Option<(f32, [usize; 3])> {
+    let pos = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(vertexs_pos) => match vertexs_pos {
+            VertexAttributeValues::Float32x3(positions) => positions,
+            _ => panic!("Mesh position type unexpected"),
+        },
+        None => panic!("Mesh doesn't have positions!"),
+    };
+    let triangles = match mesh.indices() {
+        Some(Indices::U16(inds)) => triangles(inds),
+        Some(Indices::U32(inds)) => triangles(inds),
+        None => (0..pos.len() / 3)
+            .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+            .collect(),
+    };
+    let vertex = |i: usize| Vec3A::new(pos[i][0], pos[i][1], pos[i][2]);
+    triangles
+        .into_iter()
+        .filter_map(|tri| {
+            let t = ray.intersect_tri(&[vertex(tri[0]), vertex(tri[1]), vertex(tri[2])])?;
+            Some((t, tri))
+        })
+        .filter(|(t, _)| *t >= 0.)
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+}
+
+fn triangles<I: IntoUsize>(inds: &[I]) -> Vec<[usize; 3]> {
+    inds.chunks(3)
+        .map(|index| {
+            [
+                index[0].into_usize(),
+                index[1].into_usize(),
+                index[2].into_usize(),
+            ]
+        })
+        .collect()
+}
+
 pub trait IntoUsize: Copy {
     fn into_usize(self) -> usize;
 }
@@ -211,3 +203,31 @@ impl IntoUsize for u32 {
         self as usize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::Cylinder;
+
+    #[test]
+    fn ray_hits_the_closest_triangle() {
+        let mesh = Mesh::from(Cylinder {
+            height: 1.,
+            radius: 1.,
+            segments: 6,
+        });
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions.clone(),
+            _ => unreachable!(),
+        };
+        let down = Line::new(Vec3::new(0.3, 5., 0.2), -Vec3::Y);
+        let (t, triangle) = intersect_mesh(&mesh, &down).unwrap();
+        assert!((t - 4.).abs() < 1e-5);
+        // The top face is hit before the bottom one.
+        for i in triangle {
+            assert_eq!(positions[i][1], 1.);
+        }
+        let away = Line::new(Vec3::new(0.3, 5., 0.2), Vec3::Y);
+        assert!(intersect_mesh(&mesh, &away).is_none());
+    }
+}
@@ -55,7 +55,7 @@ impl From<Cylinder> for Mesh {
 
         vertices.push([0.0, cylinder.height, 0.0]);
         let mut uvs: Vec<[f32; 2]> = Vec::new();
-        for i in 0..vertices.len() {
+        for _ in 0..vertices.len() {
             uvs.push([0., 0.]);
         }
         let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
@@ -1,12 +1,7 @@
-use std::f32::EPSILON;
-
 use bevy::{
     math::{Mat4, Vec3, Vec3A},
     prelude::Mesh,
-    render::{
-        mesh::VertexAttributeValues,
-        render_resource::{PrimitiveTopology, VertexAttribute},
-    },
+    render::{mesh::VertexAttributeValues, render_resource::PrimitiveTopology},
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -27,7 +22,8 @@ impl Line {
         }
     }
 
-    pub fn intersect_tri(&self, tri: &[Vec3A; 3]) -> Option<()> {
+    /// Parameter along `direction` at which the line crosses the front face of `tri`.
+    pub fn intersect_tri(&self, tri: &[Vec3A; 3]) -> Option<f32> {
         // Determine the plan equation
         let dir: Vec3A = self.direction.into();
         let orig: Vec3A = self.origin.into();
@@ -35,7 +31,7 @@ impl Line {
         let vector_v0_to_v2: Vec3A = tri[2] - tri[0];
         let p_vec: Vec3A = dir.cross(vector_v0_to_v2);
         let determinant: f32 = vector_v0_to_v1.dot(p_vec);
-        if determinant < EPSILON {
+        if determinant < f32::EPSILON {
             return None;
         }
 
@@ -56,11 +52,21 @@ impl Line {
         // The distance between ray origin and intersection is t.
         let t: f32 = vector_v0_to_v2.dot(q_vec) * determinant_inverse;
 
-        Some(())
+        Some(t)
     }
 
-    pub fn intersect_plane() -> bool {
-        unimplemented!()
+    /// Parameter along `direction` at which the line crosses the plane going
+    /// through `point` with the given `normal`, if they are not parallel.
+    pub fn intersect_plane(&self, point: Vec3, normal: Vec3) -> Option<f32> {
+        let denominator = self.direction.dot(normal);
+        if denominator.abs() < f32::EPSILON {
+            return None;
+        }
+        Some((point - self.origin).dot(normal) / denominator)
+    }
+
+    pub fn point_at(&self, t: f32) -> Vec3 {
+        self.origin + t * self.direction
     }
 
     pub fn line_mesh(&self, start: f32, end: f32) -> Mesh {
@@ -84,7 +90,37 @@ impl Line {
             Mesh::ATTRIBUTE_NORMAL,
             VertexAttributeValues::Float32x3(vec![[0.0, 0.0, 0.0], [0.0, 0.0, 0.0]]),
         );
-        return mesh;
+        mesh
+    }
+
+    /// Flat ribbons facing up, one per segment from the `origin` of a line to
+    /// `origin + direction`, `width` wide.
+    pub fn strip_mesh(lines: &[Line], width: f32) -> Mesh {
+        let mut positions = Vec::with_capacity(lines.len() * 6);
+        for line in lines {
+            let side = Vec3::Y.cross(line.direction).normalize_or_zero() * (width / 2.);
+            let start = line.origin;
+            let end = line.point_at(1.);
+            let quad = [start + side, start - side, end - side, end + side];
+            for i in [0, 1, 2, 0, 2, 3] {
+                positions.push(quad[i].to_array());
+            }
+        }
+        let count = positions.len();
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            VertexAttributeValues::Float32x3(positions),
+        );
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            VertexAttributeValues::Float32x3(vec![[0.0, 1.0, 0.0]; count]),
+        );
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            VertexAttributeValues::Float32x2(vec![[0.0, 0.0]; count]),
+        );
+        mesh
     }
 }
 
@@ -98,12 +134,46 @@ mod tests {
 
     #[test]
     fn raycast_triangle_mt() {
-        let V0: Vec3A = Vec3A::new(1.0, -1.0, 2.0);
-        let V1: Vec3A = Vec3A::new(1.0, 2.0, -1.0);
-        let V2: Vec3A = Vec3A::new(1.0, -1.0, -1.0);
-        let triangle = [V0, V1, V2];
+        let v0: Vec3A = Vec3A::new(1.0, -1.0, 2.0);
+        let v1: Vec3A = Vec3A::new(1.0, 2.0, -1.0);
+        let v2: Vec3A = Vec3A::new(1.0, -1.0, -1.0);
+        let triangle = [v0, v1, v2];
         let ray = Line::new(Vec3::ZERO, Vec3::X);
         let result = ray.intersect_tri(&triangle);
         assert!(result.is_some());
     }
+
+    #[test]
+    fn line_plane_intersection() {
+        let ray = Line::new(Vec3::new(1.0, 4.0, 2.0), Vec3::new(0.0, -2.0, 0.0));
+        let t = ray.intersect_plane(Vec3::ZERO, Vec3::Y).unwrap();
+        assert_eq!(t, 2.0);
+        assert_eq!(ray.point_at(t), Vec3::new(1.0, 0.0, 2.0));
+        let parallel = Line::new(Vec3::Y, Vec3::X);
+        assert!(parallel.intersect_plane(Vec3::ZERO, Vec3::Y).is_none());
+    }
+
+    #[test]
+    fn strips_face_up() {
+        let lines = [
+            Line::new(Vec3::ZERO, Vec3::X),
+            Line::new(Vec3::X, Vec3::new(0.0, 0.5, 2.0)),
+        ];
+        let mesh = Line::strip_mesh(&lines, 0.2);
+        assert_eq!(mesh.count_vertices(), 12);
+        // A ray from above hits the front faces of both strips.
+        for line in lines {
+            let above = Line::new(line.point_at(0.3) + Vec3::Y, -Vec3::Y);
+            let hit = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+                Some(VertexAttributeValues::Float32x3(positions)) => positions
+                    .chunks(3)
+                    .filter_map(|tri| {
+                        above.intersect_tri(&[tri[0].into(), tri[1].into(), tri[2].into()])
+                    })
+                    .next(),
+                _ => None,
+            };
+            assert!(hit.is_some());
+        }
+    }
 }
@@ -0,0 +1,5 @@
+pub mod player;
+pub mod tilemap;
+
+pub trait GridRayLayerT {}
+pub struct GridRayLayer;
@@ -3,21 +3,25 @@ use bevy_ext::camera::{screen_to_world_dir, PanOrbitCameraPlugin};
 use bevy_ext::raycast::{FireRay, RayHit, RayLayerPlugin};
 // use bevy_ext::debug::GridPlugin;
 
-use player::spawn_player;
-use tilemap::create_grid;
-mod player;
-mod tilemap;
-
-pub trait GridRayLayerT {}
-pub struct GridRayLayer;
+use game::player::spawn_player;
+use game::tilemap::{
+    BiomeTable, ChunkMesh, ErosionSettings, Faction, FogSettings, GeneratorSettings, Tile,
+    TileMapPlugin,
+};
+use game::GridRayLayer;
 
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
         .add_plugin(RayLayerPlugin::<GridRayLayer>::new())
         .add_startup_system(setup)
-        .add_startup_system(create_grid)
-        .add_startup_system(spawn_player)
+        .insert_resource(FogSettings {
+            faction: Some(Faction(0)),
+        })
+        .insert_resource(generator_settings())
+        .add_plugin(TileMapPlugin)
+        // After the grid is spawned, so that the player can stand on it.
+        .add_startup_system_to_stage(StartupStage::PostStartup, spawn_player)
         .add_system(ray_fired)
         .add_system(click_to_fire_ray_on_layer)
         .add_system(on_ray_hit)
@@ -34,14 +38,27 @@ pub fn setup(mut commands: Commands) {
     })
 }
 
+/// Generator settings using the biome table of the assets, so that it can be tuned without rebuilding.
+fn generator_settings() -> GeneratorSettings {
+    let mut settings = GeneratorSettings {
+        erosion: Some(ErosionSettings::default()),
+        ..Default::default()
+    };
+    match BiomeTable::load("assets/biomes.ron") {
+        Ok(biomes) => settings.climate.biomes = biomes,
+        Err(err) => warn!("Cannot load biomes, using the defaults: {}", err),
+    }
+    settings
+}
+
 pub fn ray_fired(mut events: EventReader<FireRay<GridRayLayer>>) {
-    for event in events.iter() {
+    for _ in events.iter() {
         println!("ray fired");
     }
 }
 
 pub fn click_to_fire_ray_on_layer(
-    mut windows: ResMut<Windows>,
+    windows: Res<Windows>,
     q_camera: Query<(&GlobalTransform, &Camera)>,
     mut ray_events: EventWriter<FireRay<GridRayLayer>>,
     btn: Res<Input<MouseButton>>,
@@ -55,9 +72,21 @@ pub fn click_to_fire_ray_on_layer(
     }
 }
 
-pub fn on_ray_hit(mut hits: EventReader<RayHit<GridRayLayer>>) {
+pub fn on_ray_hit(
+    mut hits: EventReader<RayHit<GridRayLayer>>,
+    meshes: Res<Assets<Mesh>>,
+    tiles: Query<&Tile>,
+    chunks: Query<(&ChunkMesh, &Handle<Mesh>)>,
+) {
     for hit in hits.iter() {
         let entity = hit.entity;
-        eprintln!("We hit something {entity:?}!");
+        let coords = match tiles.get(entity) {
+            Ok(tile) => Some(tile.coords),
+            Err(_) => chunks
+                .get(entity)
+                .ok()
+                .and_then(|(chunk, mesh)| chunk.tile(meshes.get(mesh)?, hit.triangle)),
+        };
+        eprintln!("We hit something {entity:?} on tile {coords:?}!");
     }
 }
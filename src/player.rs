@@ -3,6 +3,8 @@ use bevy::{
     render::{mesh::Indices, render_resource::PrimitiveTopology},
 };
 
+use crate::tilemap::{DefaultGrid, Faction, HexCoord, OnGrid, OnTile, Viewer};
+
 pub fn get_player_mesh(height: f32, prop: f32) -> Mesh {
     let body_width = (1. - prop) * height;
     let head_height = (1. - prop) * height;
@@ -30,7 +32,7 @@ pub fn get_player_mesh(height: f32, prop: f32) -> Mesh {
         8, 12, 10, 9, 11, 11, 9, 12,
     ];
     let mut uvs = Vec::new();
-    for i in 0..positions.len() {
+    for _ in 0..positions.len() {
         uvs.push([0.0, 0.0]);
     }
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
@@ -42,16 +44,20 @@ pub fn get_player_mesh(height: f32, prop: f32) -> Mesh {
     mesh
 }
 
+/// Marks the player's unit, which stands on the tile of its `OnTile`.
 #[derive(Component)]
-pub struct Player {
-    pub pos: IVec2,
-}
+pub struct Player;
 
 pub fn spawn_player(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    grids: Query<Entity, With<DefaultGrid>>,
 ) {
+    let grid = match grids.iter().next() {
+        Some(grid) => grid,
+        None => return,
+    };
     let player_mesh = meshes.add(get_player_mesh(1., 0.8));
     let player_material = materials.add(StandardMaterial {
         base_color: Color::rgb(1., 0., 0.),
@@ -60,7 +66,11 @@ pub fn spawn_player(
     println!("Player spawned in!");
     commands
         .spawn()
-        .insert(Player { pos: IVec2::ZERO })
+        .insert(Player)
+        .insert(OnTile(HexCoord::ZERO))
+        .insert(Faction(0))
+        .insert(Viewer::default())
+        .insert(OnGrid(grid))
         .insert_bundle(PbrBundle {
             mesh: player_mesh,
             material: player_material,
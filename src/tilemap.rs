@@ -1,12 +1,192 @@
+mod aoe;
+mod chunk;
+mod chunk_mesh;
+mod climate;
+mod edge;
+mod erosion;
+mod fog;
+mod generator;
 mod grid;
 mod heightmap;
+mod hex;
+mod highlight;
+mod layout;
+mod map_file;
+mod occupancy;
+mod pathfinding;
+mod route;
+mod sight;
+mod square;
 mod tile;
 
-use bevy::prelude::Plugin;
+pub use aoe::{facing, AoeShape};
+use bevy::prelude::*;
+pub use chunk::{Chunk, ChunkCoord, ChunkSettings};
+pub use chunk_mesh::{bake_chunk, rebake_chunks, ChunkMaterial, ChunkMaterialPlugin, ChunkMesh};
+pub use climate::{Biome, BiomeTable, Climate, ClimateSettings};
+pub use edge::{HexEdge, HexVertex};
+pub use erosion::{erode, ErosionSettings};
+pub use fog::{update_fog, Faction, Fog, FogSettings, TileVisibility, Viewer};
+pub use generator::{GeneratorSettings, MapGenerator};
 pub use grid::*;
+pub use heightmap::{Heightmap, HeightmapSettings, NoisePeriod};
+pub use hex::HexCoord;
+pub use highlight::{
+    draw_highlights, highlight_mesh, HighlightMaterial, HighlightMesh, TileHighlight,
+};
+pub use layout::{HexOrientation, Layout};
+pub use map_file::{
+    load_map, save_map, LoadMap, MapError, MapFile, SaveMap, SpawnPoint, TileRecord,
+    MAP_FORMAT_VERSION,
+};
+pub use occupancy::{
+    update_occupancy, Occupancy, OccupancyError, StackingRules, TileEntered, TileExited,
+};
+pub use pathfinding::{
+    find_path, find_unit_path, movement_range, unit_movement_range, Path, PathCosts, Reachable,
+};
+pub use route::{draw_routes, river_line, road_line, RouteMaterials, RouteMesh};
+pub use sight::{field_of_view, line_of_sight};
+pub use square::{Connectivity, SquareCoord};
+pub use tile::{Terrain, TerrainMaterials, TerrainProperties, Tile};
 
 pub struct TileMapPlugin;
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash, SystemLabel)]
+pub enum TileMapSystem {
+    MaintainGrid,
+    UpdateOccupancy,
+    UpdateFog,
+}
+
 impl Plugin for TileMapPlugin {
-    fn build(&self, app: &mut bevy::prelude::App) {}
+    fn build(&self, app: &mut App) {
+        app.add_plugin(ChunkMaterialPlugin)
+            .init_resource::<GridSettings>()
+            .init_resource::<HeightmapSettings>()
+            .init_resource::<GeneratorSettings>()
+            .init_resource::<TerrainMaterials>()
+            .init_resource::<RouteMaterials>()
+            .init_resource::<HighlightMaterial>()
+            .init_resource::<PathCosts>()
+            .init_resource::<StackingRules>()
+            .init_resource::<FogSettings>()
+            .init_resource::<ChunkSettings>()
+            .add_event::<LoadMap>()
+            .add_event::<SaveMap>()
+            .add_event::<TileEntered>()
+            .add_event::<TileExited>()
+            .add_startup_system(create_grid)
+            // Before `maintain_grid`, so that the loaded grid is filled in the same frame.
+            .add_system_to_stage(CoreStage::PreUpdate, load_map)
+            .add_system(apply_grid_settings.before(TileMapSystem::MaintainGrid))
+            .add_system(maintain_grid.label(TileMapSystem::MaintainGrid))
+            // After the grid is generated, so that new units find their tile.
+            .add_system(
+                update_occupancy
+                    .label(TileMapSystem::UpdateOccupancy)
+                    .after(TileMapSystem::MaintainGrid),
+            )
+            .add_system(save_map)
+            // After the chunks they are drawn in are spawned.
+            .add_system(draw_routes.after(TileMapSystem::MaintainGrid))
+            .add_system(draw_highlights.after(TileMapSystem::MaintainGrid))
+            // After the commands of `maintain_grid` are applied, so that new tiles get fogged too.
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_fog.label(TileMapSystem::UpdateFog),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                rebake_chunks.after(TileMapSystem::UpdateFog),
+            );
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+
+    use bevy::asset::AssetPlugin;
+
+    use super::*;
+
+    /// An app running `TileMapPlugin` without a window or renderer.
+    pub(crate) fn headless_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_plugin(TileMapPlugin);
+        app
+    }
+
+    /// A grid of level grass over every tile of `settings`, without entities.
+    pub(crate) fn flat_grid(settings: &GridSettings) -> Grid {
+        let mut grid = Grid::default();
+        for coords in settings.coords() {
+            grid.terrains.insert(coords, Terrain::Grass);
+            grid.elevations.insert(coords, 0.);
+        }
+        grid
+    }
+
+    /// Tile entities of the `DefaultGrid`.
+    pub(crate) fn grid_tiles(app: &mut App) -> HashMap<HexCoord, Entity> {
+        let mut grids = app.world.query_filtered::<&Grid, With<DefaultGrid>>();
+        grids.iter(&app.world).next().unwrap().tiles.clone()
+    }
+
+    #[test]
+    fn changing_settings_reconciles_tiles() {
+        let mut app = headless_app();
+        app.update();
+        let before = grid_tiles(&mut app);
+        assert_eq!(before.len(), 1 + 3 * 5 * 6);
+
+        app.world.get_resource_mut::<GridSettings>().unwrap().radius = 2;
+        app.update();
+        let after = grid_tiles(&mut app);
+        assert_eq!(after.len(), 1 + 3 * 2 * 3);
+        for (coords, tile) in before {
+            match after.get(&coords) {
+                Some(kept) => assert_eq!(*kept, tile),
+                None => assert!(app.world.get_entity(tile).is_none()),
+            }
+        }
+        for (coords, tile) in after {
+            assert_eq!(app.world.get::<Tile>(tile).unwrap().coords, coords);
+        }
+    }
+
+    #[test]
+    fn tiles_are_raised_to_their_elevation() {
+        let mut app = headless_app();
+        app.update();
+        let mut grids = app.world.query_filtered::<&Grid, With<DefaultGrid>>();
+        let grid = grids.iter(&app.world).next().unwrap();
+        let max_elevation = HeightmapSettings::default().max_elevation;
+        for (coords, tile) in grid.tiles.iter() {
+            let elevation = grid.elevation(*coords);
+            assert!((0. ..=max_elevation).contains(&elevation));
+            let transform = app.world.get::<Transform>(*tile).unwrap();
+            let top = transform.scale.y * TILE_HEIGHT;
+            assert!((top - (TILE_HEIGHT + elevation)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn tiles_use_their_terrain_material() {
+        let mut app = headless_app();
+        app.update();
+        let mut grids = app.world.query_filtered::<&Grid, With<DefaultGrid>>();
+        let grid = grids.iter(&app.world).next().unwrap();
+        let materials = app.world.get_resource::<TerrainMaterials>().unwrap();
+        for (coords, tile) in grid.tiles.iter() {
+            let terrain = grid.terrain(*coords).unwrap();
+            let material = app.world.get::<Handle<StandardMaterial>>(*tile).unwrap();
+            assert_eq!(*material, materials.get(terrain));
+        }
+    }
 }
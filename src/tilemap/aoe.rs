@@ -0,0 +1,166 @@
+use super::{Grid, GridSettings, HexCoord};
+
+/// Tiles hit by an ability, around the tile it is cast from.
+///
+/// Shapes are laid out on hex tiles facing `HexCoord::DIRECTIONS[0]`, and
+/// turned towards the facing direction they are cast in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AoeShape {
+    /// Every tile within `radius` steps, the origin included.
+    Radius(u32),
+    /// Every tile at exactly `radius` steps.
+    Ring(u32),
+    /// Tiles up to `length` steps ahead, in a wedge 60° wide.
+    Cone(u32),
+    /// The `length` tiles straight ahead.
+    Line(u32),
+    /// Any offsets from the origin, given facing `HexCoord::DIRECTIONS[0]`.
+    Stamp(Vec<HexCoord>),
+}
+
+impl AoeShape {
+    /// Offsets from the origin covered by the shape, facing `HexCoord::DIRECTIONS[0]`.
+    pub fn offsets(&self) -> Vec<HexCoord> {
+        let ahead = HexCoord::DIRECTIONS[0];
+        match self {
+            AoeShape::Radius(radius) => HexCoord::ZERO.spiral(*radius),
+            AoeShape::Ring(radius) => HexCoord::ZERO.ring(*radius),
+            AoeShape::Cone(length) => {
+                // Each row spreads half its distance to both sides, along the ring.
+                let (left, right) = (HexCoord::DIRECTIONS[2], HexCoord::DIRECTIONS[4]);
+                let mut offsets = Vec::new();
+                for step in 1..=*length as i32 {
+                    let center = ahead * step;
+                    offsets.push(center);
+                    for side in 1..=step / 2 {
+                        offsets.push(center + left * side);
+                        offsets.push(center + right * side);
+                    }
+                }
+                offsets
+            }
+            AoeShape::Line(length) => (1..=*length as i32).map(|step| ahead * step).collect(),
+            AoeShape::Stamp(offsets) => offsets.clone(),
+        }
+    }
+
+    /// Tiles of `grid` covered when cast from `origin` towards
+    /// `HexCoord::DIRECTIONS[facing]`, wrapped around the seams of wrapping maps.
+    pub fn tiles(
+        &self,
+        grid: &Grid,
+        settings: &GridSettings,
+        origin: HexCoord,
+        facing: usize,
+    ) -> Vec<HexCoord> {
+        let mut tiles = Vec::new();
+        for offset in self.offsets() {
+            let coords = settings.wrap(origin + offset.rotate(facing as i32));
+            // Large shapes may reach the same tile twice around a small wrapping map.
+            if grid.contains(coords) && !tiles.contains(&coords) {
+                tiles.push(coords);
+            }
+        }
+        tiles
+    }
+}
+
+/// Index in `HexCoord::DIRECTIONS` of the direction closest to the way from
+/// `from` to `to`.
+pub fn facing(from: HexCoord, to: HexCoord) -> usize {
+    let delta = to - from;
+    let cube = |coords: HexCoord| [coords.q, coords.r, coords.s()];
+    (0..6)
+        .max_by_key(|index| {
+            let dir = cube(HexCoord::DIRECTIONS[*index]);
+            let alignment: i32 = dir.iter().zip(cube(delta).iter()).map(|(a, b)| a * b).sum();
+            // Prefer the lowest direction on ties.
+            (alignment, -(*index as i32))
+        })
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::tilemap::{tests::flat_grid, WrapMode};
+
+    fn settings(radius: usize) -> GridSettings {
+        GridSettings {
+            radius,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shapes_cover_the_expected_tiles() {
+        let settings = settings(10);
+        let grid = flat_grid(&settings);
+        let count = |shape: AoeShape| shape.tiles(&grid, &settings, HexCoord::ZERO, 0).len();
+        assert_eq!(count(AoeShape::Radius(2)), 19);
+        assert_eq!(count(AoeShape::Ring(2)), 12);
+        assert_eq!(count(AoeShape::Line(3)), 3);
+        assert_eq!(count(AoeShape::Cone(4)), 1 + 3 + 3 + 5);
+
+        let cone = AoeShape::Cone(4).tiles(&grid, &settings, HexCoord::ZERO, 0);
+        for coords in &cone {
+            assert!((1..=4).contains(&coords.length()));
+            assert_eq!(facing(HexCoord::ZERO, *coords), 0);
+        }
+        let unique: HashSet<_> = cone.iter().collect();
+        assert_eq!(unique.len(), cone.len());
+    }
+
+    #[test]
+    fn shapes_turn_with_the_facing_and_are_clipped() {
+        let settings = settings(3);
+        let grid = flat_grid(&settings);
+        let origin = HexCoord::new(1, -1);
+        let stamp = AoeShape::Stamp(vec![HexCoord::new(1, 0), HexCoord::new(2, -1)]);
+        for dir in 0..6 {
+            let line = AoeShape::Line(2).tiles(&grid, &settings, origin, dir);
+            let ahead = origin + HexCoord::DIRECTIONS[dir];
+            assert_eq!(line.first(), Some(&ahead));
+            assert_eq!(facing(origin, ahead), dir);
+            assert_eq!(
+                stamp.tiles(&grid, &settings, HexCoord::ZERO, dir),
+                [
+                    HexCoord::new(1, 0).rotate(dir as i32),
+                    HexCoord::new(2, -1).rotate(dir as i32)
+                ]
+            );
+        }
+        // Only the tiles within the grid are hit.
+        let edge = HexCoord::new(3, 0);
+        assert_eq!(
+            AoeShape::Radius(1).tiles(&grid, &settings, edge, 0).len(),
+            4
+        );
+        assert!(AoeShape::Line(2)
+            .tiles(&grid, &settings, edge, 0)
+            .is_empty());
+    }
+
+    #[test]
+    fn shapes_wrap_around_the_seam() {
+        let settings = GridSettings {
+            radius: 4,
+            wrap: WrapMode::Cylinder,
+            ..Default::default()
+        };
+        let grid = flat_grid(&settings);
+        let edge = HexCoord::new(5, -3);
+        let line = AoeShape::Line(2).tiles(&grid, &settings, edge, 0);
+        assert_eq!(line.len(), 2);
+        for (step, coords) in line.iter().enumerate() {
+            assert!(grid.contains(*coords));
+            assert_eq!(settings.distance(edge, *coords), step as i32 + 1);
+        }
+        assert_eq!(
+            AoeShape::Radius(1).tiles(&grid, &settings, edge, 0).len(),
+            7
+        );
+    }
+}
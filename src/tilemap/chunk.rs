@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use super::{Grid, GridSettings, HexCoord, WrapMode};
+
+/// Position of a chunk. Chunks are `chunk_size` by `chunk_size` rhombi of
+/// tiles along `q` and `r`, which tile every layout without gaps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkCoord {
+    pub q: i32,
+    pub r: i32,
+}
+
+impl ChunkCoord {
+    pub const fn new(q: i32, r: i32) -> Self {
+        Self { q, r }
+    }
+
+    /// The chunk holding the tile at `coords`.
+    pub fn of(coords: HexCoord, chunk_size: u32) -> Self {
+        let size = chunk_size as i32;
+        Self::new(coords.q.div_euclid(size), coords.r.div_euclid(size))
+    }
+
+    /// The tile of the chunk with the lowest `q` and `r`.
+    pub fn origin(self, chunk_size: u32) -> HexCoord {
+        let size = chunk_size as i32;
+        HexCoord::new(self.q * size, self.r * size)
+    }
+
+    /// Every tile coordinate covered by the chunk, whether the grid holds it or not.
+    pub fn tiles(self, chunk_size: u32) -> impl Iterator<Item = HexCoord> {
+        let origin = self.origin(chunk_size);
+        let size = chunk_size as i32;
+        (0..size).flat_map(move |q| (0..size).map(move |r| origin + HexCoord::new(q, r)))
+    }
+
+    /// Center of the chunk, relative to the grid.
+    pub fn center(self, settings: &GridSettings, chunk_size: u32) -> Vec3 {
+        let origin = self.origin(chunk_size);
+        let last = origin + HexCoord::new(chunk_size as i32 - 1, chunk_size as i32 - 1);
+        (settings.get_global_pos(origin) + settings.get_global_pos(last)) / 2.
+    }
+}
+
+/// Parent of the tile entities of a spawned chunk.
+#[derive(Component)]
+pub struct Chunk {
+    pub coords: ChunkCoord,
+}
+
+/// How the grid is split in chunks and which of them are spawned.
+///
+/// Only the chunks around the `PanOrbitCamera` focus are spawned, the data of
+/// the others stays in the `Grid`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkSettings {
+    /// Width of a chunk, in tiles.
+    pub chunk_size: u32,
+    /// Chunks whose center lies within this distance of the focus are spawned.
+    pub view_distance: f32,
+    /// Bake the tiles of every chunk in a single mesh instead of spawning one
+    /// entity per tile. Hidden tiles are left out of the mesh and explored
+    /// ones are dimmed, and the chunks are baked again when the fog changes.
+    pub merge_meshes: bool,
+}
+
+impl Default for ChunkSettings {
+    fn default() -> Self {
+        Self {
+            chunk_size: 8,
+            view_distance: 20.,
+            merge_meshes: false,
+        }
+    }
+}
+
+impl ChunkSettings {
+    /// Chunks holding tiles of `grid` around `focus`, given relative to the grid.
+    pub fn chunks_around(
+        &self,
+        settings: &GridSettings,
+        grid: &Grid,
+        focus: Vec3,
+    ) -> HashSet<ChunkCoord> {
+        let focus = Vec3::new(focus.x, 0., focus.z);
+        let candidates: HashSet<ChunkCoord> = if settings.wrap == WrapMode::None {
+            let center = ChunkCoord::of(settings.get_grid_pos(focus), self.chunk_size);
+            // The shortest step between two tiles bounds how many chunks fit in the view distance,
+            // doubled because chunks are skewed along `q` and `r`.
+            let layout = settings.layout();
+            let step = layout
+                .to_world(HexCoord::new(1, 0))
+                .length()
+                .min(layout.to_world(HexCoord::new(0, 1)).length());
+            let reach =
+                2 * (self.view_distance / (step * self.chunk_size as f32)).ceil() as i32 + 1;
+            (-reach..=reach)
+                .flat_map(|q| {
+                    (-reach..=reach).map(move |r| ChunkCoord::new(center.q + q, center.r + r))
+                })
+                .collect()
+        } else {
+            // Every chunk of a wrapping map may have a copy near the focus.
+            settings
+                .coords()
+                .into_iter()
+                .map(|coords| ChunkCoord::of(coords, self.chunk_size))
+                .collect()
+        };
+
+        let mut chunks = HashSet::new();
+        for chunk in candidates {
+            let center = chunk.center(settings, self.chunk_size);
+            let center = center + settings.wrap_offset(center, focus);
+            if center.distance(focus) > self.view_distance {
+                continue;
+            }
+            if chunk
+                .tiles(self.chunk_size)
+                .any(|coords| grid.contains(coords))
+            {
+                chunks.insert(chunk);
+            }
+        }
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ext::camera::PanOrbitCamera;
+
+    use super::*;
+    use crate::tilemap::tests::headless_app;
+
+    #[test]
+    fn chunks_partition_the_tiles() {
+        for coords in HexCoord::ZERO.spiral(20) {
+            let chunk = ChunkCoord::of(coords, 6);
+            assert!(chunk.tiles(6).any(|tile| tile == coords));
+        }
+        assert_eq!(ChunkCoord::new(-1, 2).tiles(6).count(), 36);
+    }
+
+    #[test]
+    fn chunks_stream_around_the_camera_focus() {
+        let mut app = headless_app();
+        app.insert_resource(GridSettings {
+            radius: 30,
+            ..Default::default()
+        })
+        .insert_resource(ChunkSettings {
+            chunk_size: 4,
+            view_distance: 5.,
+            ..Default::default()
+        });
+        let camera = app.world.spawn().insert(PanOrbitCamera::default()).id();
+        app.update();
+
+        let snapshot = |app: &mut App| {
+            let mut grids = app.world.query::<&Grid>();
+            let grid = grids.iter(&app.world).next().unwrap();
+            (
+                grid.tiles.clone(),
+                grid.elevations.clone(),
+                grid.terrains.clone(),
+            )
+        };
+        let (near, elevations, terrains) = snapshot(&mut app);
+        assert!(near.contains_key(&HexCoord::ZERO));
+        assert!(!near.contains_key(&HexCoord::new(25, 0)));
+        assert_eq!(terrains.len(), 1 + 3 * 30 * 31);
+        assert!(near.len() < terrains.len() / 4);
+
+        let settings = app.world.get_resource::<GridSettings>().unwrap().clone();
+        app.world.get_mut::<PanOrbitCamera>(camera).unwrap().focus =
+            settings.get_global_pos(HexCoord::new(25, 0));
+        app.update();
+
+        let (far, far_elevations, far_terrains) = snapshot(&mut app);
+        assert!(far.contains_key(&HexCoord::new(25, 0)));
+        assert!(!far.contains_key(&HexCoord::ZERO));
+        for tile in near.values() {
+            assert!(app.world.get_entity(*tile).is_none());
+        }
+        // Unloading a chunk keeps its tile data.
+        assert_eq!(far_elevations, elevations);
+        assert_eq!(far_terrains, terrains);
+
+        app.world.get_mut::<PanOrbitCamera>(camera).unwrap().focus = Vec3::ZERO;
+        app.update();
+        let (back, _, _) = snapshot(&mut app);
+        assert_eq!(
+            back.keys().collect::<HashSet<_>>(),
+            near.keys().collect::<HashSet<_>>()
+        );
+    }
+
+    #[test]
+    fn chunks_follow_the_focus_across_the_seam() {
+        let mut app = headless_app();
+        app.insert_resource(GridSettings {
+            radius: 10,
+            wrap: WrapMode::Cylinder,
+            ..Default::default()
+        })
+        .insert_resource(ChunkSettings {
+            chunk_size: 4,
+            view_distance: 5.,
+            ..Default::default()
+        });
+        let settings = app.world.get_resource::<GridSettings>().unwrap().clone();
+        // Past the east edge, over the copy of a tile of the west edge.
+        let west = HexCoord::new(-10, 5);
+        let focus = settings.get_global_pos(west + settings.wrap_periods()[0]);
+        app.world.spawn().insert(PanOrbitCamera {
+            focus,
+            ..Default::default()
+        });
+        app.update();
+
+        let mut grids = app.world.query::<&Grid>();
+        let grid = grids.iter(&app.world).next().unwrap();
+        assert!(grid.tiles.contains_key(&west));
+        assert!(grid.tiles.contains_key(&HexCoord::new(11, -5)));
+        assert!(!grid.tiles.contains_key(&HexCoord::ZERO));
+        // The west tile is drawn next to the focus, not on the other side of the map.
+        let chunk = grid.chunks[&ChunkCoord::of(west, 4)];
+        let offset = app.world.get::<Transform>(chunk).unwrap().translation;
+        assert!((settings.get_global_pos(west) + offset - focus).length() < 1e-3);
+    }
+}
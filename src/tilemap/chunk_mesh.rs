@@ -0,0 +1,444 @@
+use bevy::{
+    asset::HandleUntyped,
+    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    pbr::{MaterialPipeline, MaterialPlugin, SpecializedMaterial},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::VertexAttributeValues,
+        render_asset::{PrepareAssetError, RenderAsset},
+        render_resource::{
+            BindGroup, BindGroupDescriptor, BindGroupLayout, BindGroupLayoutDescriptor,
+            PrimitiveTopology, RenderPipelineDescriptor, Shader, VertexAttribute,
+            VertexBufferLayout, VertexFormat, VertexStepMode,
+        },
+        renderer::RenderDevice,
+    },
+};
+
+use super::{
+    Chunk, ChunkCoord, ChunkSettings, Faction, Fog, FogSettings, Grid, GridSettings, HexCoord,
+    TileVisibility,
+};
+
+/// Index in `ChunkMesh::tiles` of the tile a vertex belongs to.
+pub const ATTRIBUTE_TILE_INDEX: &str = "Vertex_TileIndex";
+
+/// Attributes read by the chunk shader, by shader location.
+const SHADER_ATTRIBUTES: [&str; 3] = [
+    Mesh::ATTRIBUTE_POSITION,
+    Mesh::ATTRIBUTE_NORMAL,
+    Mesh::ATTRIBUTE_COLOR,
+];
+
+pub const CHUNK_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 8125427719385239416);
+
+/// Tiles baked in the mesh of a merged chunk, by tile index.
+#[derive(Component)]
+pub struct ChunkMesh {
+    pub tiles: Vec<HexCoord>,
+}
+
+impl ChunkMesh {
+    /// Tile owning a triangle of the chunk `mesh`, as reported by a ray hit.
+    pub fn tile(&self, mesh: &Mesh, triangle: [usize; 3]) -> Option<HexCoord> {
+        match mesh.attribute(ATTRIBUTE_TILE_INDEX)? {
+            VertexAttributeValues::Uint32(indices) => {
+                let index = *indices.get(triangle[0])?;
+                self.tiles.get(index as usize).copied()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Bake every tile of `chunk` held by `grid` in a single mesh, colored after
+/// their terrain as `faction` knows it: tiles it never saw are left out, and
+/// the ones out of its sight are dimmed.
+pub fn bake_chunk(
+    settings: &GridSettings,
+    grid: &Grid,
+    fog: &Fog,
+    faction: Option<Faction>,
+    chunk: ChunkCoord,
+    chunk_size: u32,
+) -> (ChunkMesh, Mesh) {
+    let tile_mesh = settings.tile_mesh();
+    let (tile_positions, tile_normals) = match (
+        tile_mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+        tile_mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+    ) {
+        (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+        ) => (positions, normals),
+        _ => panic!("Tile mesh without positions or normals"),
+    };
+    let tiles: Vec<HexCoord> = chunk
+        .tiles(chunk_size)
+        .filter(|coords| {
+            grid.contains(*coords)
+                && fog.displayed_state(faction, *coords) != TileVisibility::Hidden
+        })
+        .collect();
+
+    let vertex_count = tiles.len() * tile_positions.len();
+    let mut positions = Vec::with_capacity(vertex_count);
+    let mut normals = Vec::with_capacity(vertex_count);
+    let mut colors = Vec::with_capacity(vertex_count);
+    let mut tile_indices = Vec::with_capacity(vertex_count);
+    for (index, coords) in tiles.iter().enumerate() {
+        let matrix = settings
+            .tile_transform(*coords, grid.elevation(*coords))
+            .compute_matrix();
+        // Tiles are stretched upwards, so normals need the inverse transpose.
+        let normal_matrix = Mat3::from_mat4(matrix).inverse().transpose();
+        let properties = grid.terrains[coords].properties();
+        let color = match fog.displayed_state(faction, *coords) {
+            TileVisibility::Explored => properties.dimmed_color(),
+            _ => properties.color,
+        }
+        .as_linear_rgba_f32();
+        for (position, normal) in tile_positions.iter().zip(tile_normals) {
+            positions.push(matrix.transform_point3(Vec3::from(*position)).to_array());
+            normals.push(
+                (normal_matrix * Vec3::from(*normal))
+                    .normalize_or_zero()
+                    .to_array(),
+            );
+            colors.push(color);
+            tile_indices.push(index as u32);
+        }
+    }
+
+    (
+        ChunkMesh { tiles },
+        chunk_mesh(positions, normals, colors, tile_indices),
+    )
+}
+
+/// Bake the merged chunks of a grid again when its fog changes, or when
+/// another faction is displayed.
+pub fn rebake_chunks(
+    fog_settings: Res<FogSettings>,
+    chunk_settings: Res<ChunkSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    grids: Query<(&Grid, &GridSettings, &Fog, ChangeTrackers<Fog>)>,
+    mut chunks: Query<(&Chunk, &Handle<Mesh>, &mut ChunkMesh)>,
+) {
+    if !chunk_settings.merge_meshes {
+        return;
+    }
+    for (grid, settings, fog, fog_tracker) in grids.iter() {
+        if !fog_tracker.is_changed() && !fog_settings.is_changed() {
+            continue;
+        }
+        for chunk_entity in grid.chunks.values() {
+            let (chunk, handle, mut chunk_mesh) = match chunks.get_mut(*chunk_entity) {
+                Ok(chunk) => chunk,
+                Err(_) => continue,
+            };
+            let (baked, mesh) = bake_chunk(
+                settings,
+                grid,
+                fog,
+                fog_settings.faction,
+                chunk.coords,
+                chunk_settings.chunk_size,
+            );
+            *chunk_mesh = baked;
+            if let Some(old) = meshes.get_mut(handle) {
+                *old = mesh;
+            }
+        }
+    }
+}
+
+fn chunk_mesh(
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+    tile_indices: Vec<u32>,
+) -> Mesh {
+    // The tile mesh has its vertices duplicated, so no indices are needed.
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.set_attribute(
+        ATTRIBUTE_TILE_INDEX,
+        VertexAttributeValues::Uint32(tile_indices),
+    );
+    mesh
+}
+
+/// Layout of the vertex buffer built from a chunk `mesh`, with the attributes
+/// read by the chunk shader at their `SHADER_ATTRIBUTES` locations.
+///
+/// Bevy interleaves the attributes of a mesh sorted by name, so the offsets
+/// follow the formats of the attributes in that order.
+fn vertex_layout(mesh: &Mesh) -> VertexBufferLayout {
+    let mut names = [
+        Mesh::ATTRIBUTE_POSITION,
+        Mesh::ATTRIBUTE_NORMAL,
+        Mesh::ATTRIBUTE_COLOR,
+        ATTRIBUTE_TILE_INDEX,
+    ];
+    names.sort_unstable();
+    let mut offset = 0;
+    let mut attributes = Vec::new();
+    for name in names.iter() {
+        let format = match mesh.attribute(*name) {
+            Some(values) => VertexFormat::from(values),
+            None => continue,
+        };
+        if let Some(location) = SHADER_ATTRIBUTES.iter().position(|shader| shader == name) {
+            attributes.push(VertexAttribute {
+                format,
+                offset,
+                shader_location: location as u32,
+            });
+        }
+        offset += format.size();
+    }
+    VertexBufferLayout {
+        array_stride: offset,
+        step_mode: VertexStepMode::Vertex,
+        attributes,
+    }
+}
+
+/// Draws the vertex colors of a merged chunk mesh.
+///
+/// The PBR pipeline expects a fixed vertex layout, which the color and tile
+/// index attributes of chunk meshes don't fit in.
+#[derive(Clone, Default, TypeUuid)]
+#[uuid = "4b1c3f0e-5a55-4d7c-9a43-2f6f3b0d8e21"]
+pub struct ChunkMaterial;
+
+pub struct GpuChunkMaterial {
+    bind_group: BindGroup,
+}
+
+impl RenderAsset for ChunkMaterial {
+    type ExtractedAsset = ChunkMaterial;
+    type PreparedAsset = GpuChunkMaterial;
+    type Param = (SRes<RenderDevice>, SRes<MaterialPipeline<ChunkMaterial>>);
+
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        _: Self::ExtractedAsset,
+        (render_device, pipeline): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.material_layout,
+            entries: &[],
+        });
+        Ok(GpuChunkMaterial { bind_group })
+    }
+}
+
+impl SpecializedMaterial for ChunkMaterial {
+    type Key = ();
+
+    fn key(_: &<Self as RenderAsset>::PreparedAsset) -> Self::Key {}
+
+    fn specialize(_: Self::Key, descriptor: &mut RenderPipelineDescriptor) {
+        let mesh = chunk_mesh(Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        descriptor.vertex.buffers = vec![vertex_layout(&mesh)];
+    }
+
+    fn bind_group(material: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
+        &material.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[],
+        })
+    }
+
+    fn vertex_shader(_: &AssetServer) -> Option<Handle<Shader>> {
+        Some(CHUNK_SHADER_HANDLE.typed())
+    }
+
+    fn fragment_shader(_: &AssetServer) -> Option<Handle<Shader>> {
+        Some(CHUNK_SHADER_HANDLE.typed())
+    }
+}
+
+pub struct ChunkMaterialPlugin;
+
+impl Plugin for ChunkMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        // Without a renderer there are no shaders to register.
+        if let Some(mut shaders) = app.world.get_resource_mut::<Assets<Shader>>() {
+            shaders.set_untracked(
+                CHUNK_SHADER_HANDLE,
+                Shader::from_wgsl(include_str!("chunk_mesh.wgsl")),
+            );
+        }
+        app.add_plugin(MaterialPlugin::<ChunkMaterial>::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ext::{raycast::intersect_mesh, shape::Line};
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::tilemap::{
+        tests::{flat_grid, headless_app},
+        DefaultGrid, OnGrid, OnTile, Viewer,
+    };
+
+    #[test]
+    fn merged_chunks_map_ray_hits_to_their_tiles() {
+        let mut app = headless_app();
+        app.insert_resource(ChunkSettings {
+            chunk_size: 4,
+            merge_meshes: true,
+            ..Default::default()
+        });
+        app.update();
+
+        let mut grids = app.world.query::<&Grid>();
+        let mut chunks = app
+            .world
+            .query_filtered::<(&ChunkMesh, &Handle<Mesh>), With<Chunk>>();
+        let grid = grids.iter(&app.world).next().unwrap();
+        assert!(grid.tiles.is_empty());
+        let settings = app.world.get_resource::<GridSettings>().unwrap();
+        let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+        let mut baked = 0;
+        for (chunk_mesh, mesh) in chunks.iter(&app.world) {
+            let mesh = meshes.get(mesh).unwrap();
+            baked += chunk_mesh.tiles.len();
+            for coords in chunk_mesh.tiles.iter() {
+                let pos = settings.get_global_pos(*coords) + Vec3::new(0.05, 5., 0.03);
+                let (_, triangle) = intersect_mesh(mesh, &Line::new(pos, -Vec3::Y)).unwrap();
+                assert_eq!(chunk_mesh.tile(mesh, triangle), Some(*coords));
+            }
+        }
+        assert_eq!(baked, 1 + 3 * 5 * 6);
+    }
+
+    /// Color of every tile baked in the chunks of `grid`.
+    fn baked_colors(app: &mut App, grid: Entity) -> HashMap<HexCoord, [f32; 4]> {
+        let mut chunks = app.world.query::<(&ChunkMesh, &Handle<Mesh>)>();
+        let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+        let mut colors = HashMap::new();
+        for chunk in app.world.get::<Grid>(grid).unwrap().chunks.values() {
+            let (chunk_mesh, mesh) = chunks.get(&app.world, *chunk).unwrap();
+            let mesh = meshes.get(mesh).unwrap();
+            let (tile_colors, indices) = match (
+                mesh.attribute(Mesh::ATTRIBUTE_COLOR),
+                mesh.attribute(ATTRIBUTE_TILE_INDEX),
+            ) {
+                (
+                    Some(VertexAttributeValues::Float32x4(colors)),
+                    Some(VertexAttributeValues::Uint32(indices)),
+                ) => (colors, indices),
+                _ => panic!("Chunk mesh without colors or tile indices"),
+            };
+            for (color, index) in tile_colors.iter().zip(indices) {
+                colors.insert(chunk_mesh.tiles[*index as usize], *color);
+            }
+        }
+        colors
+    }
+
+    #[test]
+    fn merged_chunks_show_what_the_faction_knows() {
+        let mut app = headless_app();
+        app.insert_resource(ChunkSettings {
+            chunk_size: 4,
+            merge_meshes: true,
+            ..Default::default()
+        })
+        .insert_resource(FogSettings {
+            faction: Some(Faction(0)),
+        });
+        app.update();
+        let mut grids = app.world.query_filtered::<Entity, With<DefaultGrid>>();
+        let grid = grids.iter(&app.world).next().unwrap();
+        assert!(baked_colors(&mut app, grid).is_empty());
+
+        let unit = app
+            .world
+            .spawn()
+            .insert(OnTile(HexCoord::ZERO))
+            .insert(Faction(0))
+            .insert(Viewer {
+                radius: 1,
+                eye_height: 0.5,
+            })
+            .insert(OnGrid(grid))
+            .id();
+        app.update();
+        app.world.get_mut::<OnTile>(unit).unwrap().0 = HexCoord::new(4, 0);
+        app.update();
+
+        let colors = baked_colors(&mut app, grid);
+        let properties = |coords| {
+            app.world
+                .get::<Grid>(grid)
+                .unwrap()
+                .terrain(coords)
+                .unwrap()
+                .properties()
+        };
+        assert_eq!(
+            colors[&HexCoord::ZERO],
+            properties(HexCoord::ZERO)
+                .dimmed_color()
+                .as_linear_rgba_f32()
+        );
+        assert_eq!(
+            colors[&HexCoord::new(4, 0)],
+            properties(HexCoord::new(4, 0)).color.as_linear_rgba_f32()
+        );
+        assert!(!colors.contains_key(&HexCoord::new(-4, 0)));
+    }
+
+    #[test]
+    fn vertex_layout_matches_the_vertex_buffer() {
+        let settings = GridSettings::default();
+        let grid = flat_grid(&settings);
+        let (_, mesh) = bake_chunk(
+            &settings,
+            &grid,
+            &Fog::default(),
+            None,
+            ChunkCoord { q: 0, r: 0 },
+            4,
+        );
+        let layout = vertex_layout(&mesh);
+        let buffer = mesh.get_vertex_buffer_data();
+        assert_eq!(
+            buffer.len() as u64,
+            mesh.count_vertices() as u64 * layout.array_stride
+        );
+
+        assert_eq!(layout.attributes.len(), SHADER_ATTRIBUTES.len());
+        let vertex = 7;
+        for attribute in layout.attributes.iter() {
+            let name = SHADER_ATTRIBUTES[attribute.shader_location as usize];
+            let bytes = mesh.attribute(name).unwrap().get_bytes();
+            let size = attribute.format.size() as usize;
+            let start = vertex * layout.array_stride as usize + attribute.offset as usize;
+            assert_eq!(
+                &buffer[start..start + size],
+                &bytes[vertex * size..(vertex + 1) * size]
+            );
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::{error::Error, fs, path::Path};
+
+use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use super::{Heightmap, HeightmapSettings, NoisePeriod, Terrain};
+
+/// Terrain of the land tiles whose climate falls in the given ranges.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Biome {
+    pub terrain: Terrain,
+    /// Lowest and highest temperature, in `[0, 1]`.
+    pub temperature: (f32, f32),
+    /// Lowest and highest moisture, in `[0, 1]`.
+    pub moisture: (f32, f32),
+}
+
+impl Biome {
+    pub fn contains(&self, temperature: f32, moisture: f32) -> bool {
+        (self.temperature.0..=self.temperature.1).contains(&temperature)
+            && (self.moisture.0..=self.moisture.1).contains(&moisture)
+    }
+}
+
+/// Biomes tried in order, the first matching one gives the terrain of a tile.
+///
+/// The game loads it from `assets/biomes.ron`, which can be tuned without
+/// rebuilding.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BiomeTable {
+    pub biomes: Vec<Biome>,
+    /// Terrain of the tiles no biome matches.
+    pub fallback: Terrain,
+}
+
+impl Default for BiomeTable {
+    fn default() -> Self {
+        let biome = |terrain, temperature, moisture| Biome {
+            terrain,
+            temperature,
+            moisture,
+        };
+        Self {
+            biomes: vec![
+                biome(Terrain::Snow, (0., 0.2), (0., 1.)),
+                biome(Terrain::Desert, (0.6, 1.), (0., 0.3)),
+                biome(Terrain::Forest, (0.2, 1.), (0.55, 1.)),
+            ],
+            fallback: Terrain::Grass,
+        }
+    }
+}
+
+impl BiomeTable {
+    pub fn terrain(&self, temperature: f32, moisture: f32) -> Terrain {
+        self.biomes
+            .iter()
+            .find(|biome| biome.contains(temperature, moisture))
+            .map_or(self.fallback, |biome| biome.terrain)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ron::Error> {
+        ron::de::from_str(text)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_ron(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// How temperature and moisture vary over the map.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClimateSettings {
+    /// Distance from the equator, along Z and in tile sizes, at which the poles are.
+    pub latitude_span: f32,
+    /// How much latitude weighs in the temperature, against noise.
+    pub latitude_weight: f32,
+    /// Temperature lost per fraction of the highest elevation above the sea.
+    pub lapse_rate: f32,
+    /// Moisture lost per fraction of the highest elevation above the sea.
+    pub drying_rate: f32,
+    pub biomes: BiomeTable,
+}
+
+impl Default for ClimateSettings {
+    fn default() -> Self {
+        Self {
+            latitude_span: 12.,
+            latitude_weight: 0.6,
+            lapse_rate: 0.6,
+            drying_rate: 0.3,
+            biomes: BiomeTable::default(),
+        }
+    }
+}
+
+/// Temperature and moisture fields of a map.
+pub struct Climate {
+    settings: ClimateSettings,
+    temperature: Heightmap,
+    moisture: Heightmap,
+}
+
+impl Climate {
+    /// Climate noise follows the shape of the heightmap, at a lower frequency.
+    pub fn new(
+        settings: &ClimateSettings,
+        heightmap: &HeightmapSettings,
+        temperature_seed: i64,
+        moisture_seed: i64,
+    ) -> Self {
+        let noise = HeightmapSettings {
+            frequency: heightmap.frequency / 2.,
+            max_elevation: 1.,
+            ..heightmap.clone()
+        };
+        Self {
+            settings: settings.clone(),
+            temperature: Heightmap::new(&noise, temperature_seed),
+            moisture: Heightmap::new(&noise, moisture_seed),
+        }
+    }
+
+    /// Temperature and moisture, both in `[0, 1]`, at `pos` given in tile sizes,
+    /// for a tile `altitude` above the sea as a fraction of the highest elevation.
+    pub fn sample(&self, pos: Vec2, period: NoisePeriod, altitude: f32) -> (f32, f32) {
+        let settings = &self.settings;
+        let latitude = (pos.y.abs() / settings.latitude_span).min(1.);
+        let altitude = altitude.max(0.);
+        let temperature = (1. - settings.latitude_weight)
+            * self.temperature.sample_wrapped(pos, period)
+            + settings.latitude_weight * (1. - latitude)
+            - settings.lapse_rate * altitude;
+        let moisture = self.moisture.sample_wrapped(pos, period) - settings.drying_rate * altitude;
+        (temperature.clamp(0., 1.), moisture.clamp(0., 1.))
+    }
+
+    pub fn terrain(&self, pos: Vec2, period: NoisePeriod, altitude: f32) -> Terrain {
+        let (temperature, moisture) = self.sample(pos, period, altitude);
+        self.settings.biomes.terrain(temperature, moisture)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_biome_table_is_the_default() {
+        let table = BiomeTable::from_ron(include_str!("../../assets/biomes.ron")).unwrap();
+        assert_eq!(table, BiomeTable::default());
+    }
+
+    #[test]
+    fn poles_and_peaks_are_cold() {
+        let climate = Climate::new(
+            &ClimateSettings::default(),
+            &HeightmapSettings::default(),
+            1,
+            2,
+        );
+        let period = NoisePeriod::default();
+        for x in 0..20 {
+            let x = x as f32 * 1.7;
+            let (equator, _) = climate.sample(Vec2::new(x, 0.), period, 0.);
+            let (pole, _) = climate.sample(Vec2::new(x, 30.), period, 0.);
+            let (peak, _) = climate.sample(Vec2::new(x, 0.), period, 0.6);
+            assert!(pole < equator);
+            assert!(peak < equator);
+            assert_eq!(
+                climate.terrain(Vec2::new(x, -30.), period, 0.5),
+                Terrain::Snow
+            );
+        }
+    }
+
+    #[test]
+    fn first_matching_biome_wins() {
+        let table = BiomeTable {
+            biomes: vec![
+                Biome {
+                    terrain: Terrain::Desert,
+                    temperature: (0.5, 1.),
+                    moisture: (0., 0.5),
+                },
+                Biome {
+                    terrain: Terrain::Forest,
+                    temperature: (0., 1.),
+                    moisture: (0.4, 1.),
+                },
+            ],
+            fallback: Terrain::Grass,
+        };
+        assert_eq!(table.terrain(0.8, 0.45), Terrain::Desert);
+        assert_eq!(table.terrain(0.2, 0.45), Terrain::Forest);
+        assert_eq!(table.terrain(0.2, 0.1), Terrain::Grass);
+    }
+}
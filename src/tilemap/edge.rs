@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+use super::HexCoord;
+
+/// Side shared by two adjacent hex tiles.
+///
+/// Every edge has a single representation: [`HexEdge::new`] stores it from
+/// the tile on its `0..3` side, so edges can be compared and hashed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct HexEdge {
+    pub coords: HexCoord,
+    /// Index in `HexCoord::DIRECTIONS` of the other tile, in `0..3`.
+    pub dir: u8,
+}
+
+/// Corner shared by three hex tiles.
+///
+/// Like edges, every vertex has a single representation: [`HexVertex::new`]
+/// stores it as corner `0` or `1` of one of its tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct HexVertex {
+    pub coords: HexCoord,
+    /// Corner `i` lies between the neighbors in `HexCoord::DIRECTIONS[i]` and
+    /// `HexCoord::DIRECTIONS[i + 1]`.
+    pub corner: u8,
+}
+
+impl HexEdge {
+    /// Edge between `coords` and its neighbor in direction `dir`.
+    pub fn new(coords: HexCoord, dir: usize) -> Self {
+        let dir = dir % 6;
+        if dir < 3 {
+            Self {
+                coords,
+                dir: dir as u8,
+            }
+        } else {
+            Self {
+                coords: coords.neighbor(dir),
+                dir: (dir - 3) as u8,
+            }
+        }
+    }
+
+    /// Edge between two tiles, if they are adjacent.
+    pub fn between(a: HexCoord, b: HexCoord) -> Option<Self> {
+        HexCoord::DIRECTIONS
+            .iter()
+            .position(|dir| a + *dir == b)
+            .map(|dir| Self::new(a, dir))
+    }
+
+    /// The two tiles on either side of the edge.
+    pub fn tiles(self) -> [HexCoord; 2] {
+        [self.coords, self.coords.neighbor(self.dir as usize)]
+    }
+
+    /// The two ends of the edge.
+    pub fn vertices(self) -> [HexVertex; 2] {
+        let dir = self.dir as usize;
+        [
+            HexVertex::new(self.coords, dir + 5),
+            HexVertex::new(self.coords, dir),
+        ]
+    }
+}
+
+impl HexVertex {
+    /// Corner `corner` of the tile `coords`, see [`HexVertex::corner`].
+    pub fn new(coords: HexCoord, corner: usize) -> Self {
+        // Corners 2 to 5 are corners 0 or 1 of the tile across one of their edges.
+        let (offset, corner) = match corner % 6 {
+            0 => (HexCoord::ZERO, 0),
+            1 => (HexCoord::ZERO, 1),
+            2 => (HexCoord::DIRECTIONS[3], 0),
+            3 => (HexCoord::DIRECTIONS[4], 1),
+            4 => (HexCoord::DIRECTIONS[4], 0),
+            _ => (HexCoord::DIRECTIONS[5], 1),
+        };
+        Self {
+            coords: coords + offset,
+            corner,
+        }
+    }
+
+    /// The three tiles meeting at the vertex.
+    pub fn tiles(self) -> [HexCoord; 3] {
+        let corner = self.corner as usize;
+        [
+            self.coords,
+            self.coords.neighbor(corner),
+            self.coords.neighbor(corner + 1),
+        ]
+    }
+
+    /// The three edges meeting at the vertex.
+    pub fn edges(self) -> [HexEdge; 3] {
+        let [a, b, c] = self.tiles();
+        [(a, b), (b, c), (c, a)].map(|(from, to)| HexEdge::between(from, to).unwrap())
+    }
+
+    /// Edge from `self` to an adjacent vertex.
+    pub fn edge_to(self, other: HexVertex) -> Option<HexEdge> {
+        self.edges()
+            .iter()
+            .find(|edge| edge.vertices().contains(&other) && other != self)
+            .copied()
+    }
+
+    /// The three vertices one edge away.
+    pub fn neighbors(self) -> [HexVertex; 3] {
+        self.edges().map(|edge| {
+            let [start, end] = edge.vertices();
+            if start == self {
+                end
+            } else {
+                start
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn coord() -> impl Strategy<Value = HexCoord> {
+        (-1000..1000, -1000..1000).prop_map(|(q, r)| HexCoord::new(q, r))
+    }
+
+    proptest! {
+        #[test]
+        fn edges_are_shared_by_both_tiles(a in coord(), dir in 0usize..6) {
+            let edge = HexEdge::new(a, dir);
+            prop_assert_eq!(edge, HexEdge::new(a.neighbor(dir), dir + 3));
+            prop_assert_eq!(HexEdge::between(a, a.neighbor(dir)), Some(edge));
+            prop_assert!(edge.tiles().contains(&a));
+            prop_assert!(edge.tiles().contains(&a.neighbor(dir)));
+        }
+
+        #[test]
+        fn vertices_are_shared_by_three_tiles(a in coord(), corner in 0usize..6) {
+            let vertex = HexVertex::new(a, corner);
+            let tiles = vertex.tiles();
+            prop_assert!(tiles.contains(&a));
+            prop_assert!(tiles.contains(&a.neighbor(corner)));
+            prop_assert!(tiles.contains(&a.neighbor(corner + 1)));
+            let edge = HexEdge::new(a, corner);
+            prop_assert!(edge.vertices().contains(&vertex));
+            prop_assert!(vertex.edges().contains(&edge));
+            for neighbor in vertex.neighbors() {
+                prop_assert!(vertex.edge_to(neighbor).is_some());
+            }
+            prop_assert_eq!(vertex.edge_to(vertex), None);
+        }
+
+        #[test]
+        fn every_tile_has_six_edges_and_vertices(a in coord()) {
+            let edges: HashSet<_> = (0..6).map(|dir| HexEdge::new(a, dir)).collect();
+            let vertices: HashSet<_> = (0..6).map(|corner| HexVertex::new(a, corner)).collect();
+            prop_assert_eq!(edges.len(), 6);
+            prop_assert_eq!(vertices.len(), 6);
+            for vertex in vertices {
+                for neighbor in vertex.neighbors() {
+                    prop_assert!(neighbor != vertex);
+                    prop_assert!(neighbor.neighbors().contains(&vertex));
+                }
+            }
+        }
+    }
+}
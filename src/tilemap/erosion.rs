@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::{GridSettings, HexCoord};
+
+/// Longest way a droplet flows before it evaporates.
+const MAX_DROPLET_STEPS: usize = 32;
+
+/// How much sediment a droplet carries per unit of water and slope.
+const SEDIMENT_CAPACITY: f32 = 4.;
+
+/// Erosion applied to generated heightmaps. Heights and slopes are fractions
+/// of the highest elevation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErosionSettings {
+    /// Passes over the whole heightmap. Every pass drops one droplet per tile.
+    pub iterations: u32,
+    /// Fraction of the slope above `talus` that slides down on every pass.
+    pub thermal_strength: f32,
+    /// Steepest slope between adjacent tiles that doesn't slide.
+    pub talus: f32,
+    /// Fraction of the missing sediment a droplet picks up on every step.
+    pub hydraulic_strength: f32,
+    /// Fraction of the excess sediment a droplet drops on every step.
+    pub deposition: f32,
+    /// Fraction of the water of a droplet lost on every step.
+    pub evaporation: f32,
+}
+
+impl Default for ErosionSettings {
+    fn default() -> Self {
+        Self {
+            iterations: 20,
+            thermal_strength: 0.5,
+            talus: 0.08,
+            hydraulic_strength: 0.3,
+            deposition: 0.3,
+            evaporation: 0.1,
+        }
+    }
+}
+
+/// Erode the `elevations` of every tile of the grid, in place.
+///
+/// Tiles are always visited in the order of `GridSettings::coords` and
+/// droplets fall where the `seed` says, so the result only depends on the
+/// settings and the seed.
+pub fn erode(
+    settings: &ErosionSettings,
+    grid_settings: &GridSettings,
+    max_elevation: f32,
+    seed: u64,
+    elevations: &mut HashMap<HexCoord, f32>,
+) {
+    if max_elevation <= 0. {
+        return;
+    }
+    let coords = grid_settings.coords();
+    let mut heights: HashMap<HexCoord, f32> = coords
+        .iter()
+        .map(|coords| (*coords, elevations[coords] / max_elevation))
+        .collect();
+    let neighbors: HashMap<HexCoord, Vec<HexCoord>> = coords
+        .iter()
+        .map(|coords| {
+            let on_grid = grid_settings
+                .neighbors(*coords)
+                .into_iter()
+                .filter(|neighbor| heights.contains_key(neighbor))
+                .collect();
+            (*coords, on_grid)
+        })
+        .collect();
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    for _ in 0..settings.iterations {
+        thermal_pass(settings, &coords, &neighbors, &mut heights);
+        for _ in 0..coords.len() {
+            let start = coords[rng.gen_range(0..coords.len())];
+            drop_droplet(settings, &neighbors, &mut heights, start);
+        }
+    }
+
+    for (coords, height) in heights {
+        elevations.insert(coords, height.clamp(0., 1.) * max_elevation);
+    }
+}
+
+/// Material slides from every tile down to the neighbors it is too steep for.
+fn thermal_pass(
+    settings: &ErosionSettings,
+    coords: &[HexCoord],
+    neighbors: &HashMap<HexCoord, Vec<HexCoord>>,
+    heights: &mut HashMap<HexCoord, f32>,
+) {
+    // Moves are applied once every tile is visited, so that the order doesn't matter.
+    let mut deltas: HashMap<HexCoord, f32> = HashMap::new();
+    for tile in coords {
+        let height = heights[tile];
+        let tile_neighbors = &neighbors[tile];
+        for neighbor in tile_neighbors {
+            let slope = height - heights[neighbor];
+            if slope <= settings.talus {
+                continue;
+            }
+            // Moving half the excess would level the pair, spread it over every neighbor.
+            let moved = settings.thermal_strength * (slope - settings.talus)
+                / 2.
+                / tile_neighbors.len() as f32;
+            *deltas.entry(*tile).or_default() -= moved;
+            *deltas.entry(*neighbor).or_default() += moved;
+        }
+    }
+    for tile in coords {
+        if let Some(delta) = deltas.get(tile) {
+            *heights.get_mut(tile).unwrap() += delta;
+        }
+    }
+}
+
+/// A droplet flows down the steepest way from `start`, picking sediment up
+/// on slopes and dropping it as it slows down or dries up.
+fn drop_droplet(
+    settings: &ErosionSettings,
+    neighbors: &HashMap<HexCoord, Vec<HexCoord>>,
+    heights: &mut HashMap<HexCoord, f32>,
+    start: HexCoord,
+) {
+    let mut tile = start;
+    let mut water = 1.;
+    let mut sediment = 0.;
+    for _ in 0..MAX_DROPLET_STEPS {
+        let height = heights[&tile];
+        let lowest = neighbors[&tile]
+            .iter()
+            .map(|neighbor| (*neighbor, heights[neighbor]))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let (next, next_height) = match lowest {
+            Some(lowest) if lowest.1 < height => lowest,
+            // A pit, which the droplet fills.
+            _ => break,
+        };
+        let slope = height - next_height;
+        let capacity = slope * water * SEDIMENT_CAPACITY;
+        let change = if sediment > capacity {
+            (sediment - capacity) * settings.deposition
+        } else {
+            // Never dig below the next tile, which would make a pit.
+            -((capacity - sediment) * settings.hydraulic_strength).min(slope)
+        };
+        *heights.get_mut(&tile).unwrap() += change;
+        sediment -= change;
+        tile = next;
+        water *= 1. - settings.evaporation;
+    }
+    *heights.get_mut(&tile).unwrap() += sediment;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::{Heightmap, HeightmapSettings};
+
+    fn noise(grid_settings: &GridSettings) -> HashMap<HexCoord, f32> {
+        let heightmap = Heightmap::new(&HeightmapSettings::default(), 5);
+        grid_settings
+            .coords()
+            .into_iter()
+            .map(|coords| (coords, heightmap.sample(grid_settings.noise_pos(coords))))
+            .collect()
+    }
+
+    fn steepest(grid_settings: &GridSettings, elevations: &HashMap<HexCoord, f32>) -> f32 {
+        elevations
+            .iter()
+            .flat_map(|(coords, height)| {
+                grid_settings
+                    .neighbors(*coords)
+                    .into_iter()
+                    .filter_map(move |neighbor| Some(height - elevations.get(&neighbor)?))
+            })
+            .fold(0., f32::max)
+    }
+
+    #[test]
+    fn erosion_is_seeded_and_smooths() {
+        let grid_settings = GridSettings {
+            radius: 8,
+            ..Default::default()
+        };
+        let settings = ErosionSettings::default();
+        let raw = noise(&grid_settings);
+        let eroded = |seed| {
+            let mut elevations = raw.clone();
+            erode(&settings, &grid_settings, 1., seed, &mut elevations);
+            elevations
+        };
+        let a = eroded(1);
+        assert_eq!(a, eroded(1));
+        assert_ne!(a, eroded(2));
+        assert_eq!(a.len(), raw.len());
+        assert!(a.values().all(|height| (0. ..=1.).contains(height)));
+        assert!(steepest(&grid_settings, &a) < steepest(&grid_settings, &raw));
+
+        // Material is moved around, not created.
+        let total = |elevations: &HashMap<HexCoord, f32>| elevations.values().sum::<f32>();
+        assert!((total(&a) - total(&raw)).abs() < 1e-2);
+    }
+
+    #[test]
+    fn no_iterations_leave_the_heightmap_alone() {
+        let grid_settings = GridSettings::default();
+        let raw = noise(&grid_settings);
+        let mut elevations = raw.clone();
+        let settings = ErosionSettings {
+            iterations: 0,
+            ..Default::default()
+        };
+        erode(&settings, &grid_settings, 1., 1, &mut elevations);
+        assert_eq!(elevations, raw);
+    }
+}
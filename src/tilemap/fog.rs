@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{field_of_view, Grid, GridSettings, HexCoord, OnGrid, OnTile, TerrainMaterials, Tile};
+
+#[derive(
+    Component, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct Faction(pub u32);
+
+/// How far a unit sees.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Viewer {
+    pub radius: u32,
+    /// Height of the eye above the tile the unit stands on.
+    pub eye_height: f32,
+}
+
+impl Default for Viewer {
+    fn default() -> Self {
+        Self {
+            radius: 3,
+            eye_height: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileVisibility {
+    /// Never seen.
+    Hidden,
+    /// Seen before but out of sight now.
+    Explored,
+    /// In sight of a unit of the faction.
+    Visible,
+}
+
+/// What every faction sees of a grid and remembers of it.
+#[derive(Component, Default)]
+pub struct Fog {
+    visible: HashMap<Faction, HashSet<HexCoord>>,
+    explored: HashMap<Faction, HashSet<HexCoord>>,
+}
+
+impl Fog {
+    pub fn state(&self, faction: Faction, coords: HexCoord) -> TileVisibility {
+        let contains = |sets: &HashMap<Faction, HashSet<HexCoord>>| {
+            sets.get(&faction)
+                .is_some_and(|tiles| tiles.contains(&coords))
+        };
+        if contains(&self.visible) {
+            TileVisibility::Visible
+        } else if contains(&self.explored) {
+            TileVisibility::Explored
+        } else {
+            TileVisibility::Hidden
+        }
+    }
+
+    /// Replace what `faction` sees. Everything it sees is remembered as explored.
+    pub fn update(&mut self, faction: Faction, visible: HashSet<HexCoord>) {
+        self.explored
+            .entry(faction)
+            .or_default()
+            .extend(visible.iter().copied());
+        self.visible.insert(faction, visible);
+    }
+
+    /// State of a tile as shown to the displayed `faction`. With `None` every
+    /// tile is visible.
+    pub fn displayed_state(&self, faction: Option<Faction>, coords: HexCoord) -> TileVisibility {
+        faction.map_or(TileVisibility::Visible, |faction| {
+            self.state(faction, coords)
+        })
+    }
+}
+
+/// Whose view of the map is displayed. With `None` every tile is shown.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FogSettings {
+    pub faction: Option<Faction>,
+}
+
+/// Recompute what every faction sees and show the tiles as the displayed faction knows them.
+///
+/// Viewers that are despawned or lose one of their components stop revealing tiles too.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn update_fog(
+    settings: Res<FogSettings>,
+    materials: Res<TerrainMaterials>,
+    units: Query<(&OnTile, &Faction, &Viewer, &OnGrid)>,
+    moved: Query<
+        (),
+        Or<(
+            Changed<OnTile>,
+            Changed<Faction>,
+            Changed<Viewer>,
+            Changed<OnGrid>,
+        )>,
+    >,
+    removed_tile: RemovedComponents<OnTile>,
+    removed_faction: RemovedComponents<Faction>,
+    removed_viewer: RemovedComponents<Viewer>,
+    removed_grid: RemovedComponents<OnGrid>,
+    mut grids: Query<(Entity, &Grid, &GridSettings, &mut Fog, ChangeTrackers<Grid>)>,
+    mut tiles: Query<(&mut Visibility, &mut Handle<StandardMaterial>), With<Tile>>,
+) {
+    let removed = removed_tile.iter().next().is_some()
+        || removed_faction.iter().next().is_some()
+        || removed_viewer.iter().next().is_some()
+        || removed_grid.iter().next().is_some();
+    for (grid_entity, grid, grid_settings, mut fog, grid_tracker) in grids.iter_mut() {
+        if moved.is_empty() && !removed && !settings.is_changed() && !grid_tracker.is_changed() {
+            continue;
+        }
+        // Only the units standing on this grid see its tiles.
+        let mut seen: HashMap<Faction, HashSet<HexCoord>> = HashMap::new();
+        for (on_tile, faction, viewer, _) in units
+            .iter()
+            .filter(|(.., on_grid)| on_grid.0 == grid_entity)
+        {
+            seen.entry(*faction).or_default().extend(field_of_view(
+                grid,
+                grid_settings,
+                on_tile.0,
+                viewer.radius,
+                viewer.eye_height,
+            ));
+        }
+        // A faction without units left keeps its memory but sees nothing.
+        let factions: HashSet<Faction> = fog.visible.keys().chain(seen.keys()).copied().collect();
+        for faction in factions {
+            fog.update(faction, seen.remove(&faction).unwrap_or_default());
+        }
+
+        for (coords, tile) in grid.tiles.iter() {
+            let (mut visibility, mut material) = match tiles.get_mut(*tile) {
+                Ok(tile) => tile,
+                Err(_) => continue,
+            };
+            let terrain = match grid.terrain(*coords) {
+                Some(terrain) => terrain,
+                None => continue,
+            };
+            let state = fog.displayed_state(settings.faction, *coords);
+            let is_visible = state != TileVisibility::Hidden;
+            if visibility.is_visible != is_visible {
+                visibility.is_visible = is_visible;
+            }
+            let wanted = match state {
+                TileVisibility::Explored => materials.get_dimmed(terrain),
+                _ => materials.get(terrain),
+            };
+            if *material != wanted {
+                *material = wanted;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+    use crate::tilemap::{spawn_grid, tests::headless_app, DefaultGrid, GridSettings};
+
+    fn tile_state(app: &mut App, coords: HexCoord) -> (bool, Handle<StandardMaterial>) {
+        let mut grids = app.world.query::<&Grid>();
+        let tile = grids.iter(&app.world).next().unwrap().tiles[&coords];
+        let visibility = app.world.get::<Visibility>(tile).unwrap().is_visible;
+        let material = app
+            .world
+            .get::<Handle<StandardMaterial>>(tile)
+            .unwrap()
+            .clone();
+        (visibility, material)
+    }
+
+    #[test]
+    fn tiles_are_revealed_and_remembered() {
+        let mut app = headless_app();
+        app.insert_resource(FogSettings {
+            faction: Some(Faction(0)),
+        });
+        app.update();
+        // Flatten the map so that only distance matters.
+        let mut grids = app.world.query::<(Entity, &mut Grid)>();
+        let (grid, _) = grids.iter(&app.world).next().unwrap();
+        for (_, mut grid) in grids.iter_mut(&mut app.world) {
+            for elevation in grid.elevations.values_mut() {
+                *elevation = 0.;
+            }
+        }
+        let unit = app
+            .world
+            .spawn()
+            .insert(OnTile(HexCoord::new(-3, 0)))
+            .insert(Faction(0))
+            .insert(OnGrid(grid))
+            .insert(Viewer {
+                radius: 1,
+                eye_height: 0.5,
+            })
+            .id();
+        // An enemy sees the other side of the map, which must not reveal it.
+        app.world
+            .spawn()
+            .insert(OnTile(HexCoord::new(3, 0)))
+            .insert(Faction(1))
+            .insert(Viewer::default())
+            .insert(OnGrid(grid));
+        app.update();
+
+        let (visible, _) = tile_state(&mut app, HexCoord::new(-3, 1));
+        assert!(visible);
+        let (visible, _) = tile_state(&mut app, HexCoord::new(3, 0));
+        assert!(!visible);
+
+        app.world.get_mut::<OnTile>(unit).unwrap().0 = HexCoord::new(0, 0);
+        app.update();
+
+        let old = HexCoord::new(-4, 0);
+        let mut fogs = app.world.query::<&Fog>();
+        let fog = fogs.iter(&app.world).next().unwrap();
+        assert_eq!(fog.state(Faction(0), old), TileVisibility::Explored);
+        assert_eq!(
+            fog.state(Faction(0), HexCoord::new(1, 0)),
+            TileVisibility::Visible
+        );
+        assert_eq!(
+            fog.state(Faction(0), HexCoord::new(4, 0)),
+            TileVisibility::Hidden
+        );
+        assert_eq!(
+            fog.state(Faction(1), HexCoord::new(4, 0)),
+            TileVisibility::Visible
+        );
+
+        let mut grids = app.world.query::<&Grid>();
+        let terrain = grids.iter(&app.world).next().unwrap().terrain(old).unwrap();
+        let materials = app.world.get_resource::<TerrainMaterials>().unwrap();
+        let dimmed = materials.get_dimmed(terrain);
+        assert_eq!(tile_state(&mut app, old), (true, dimmed));
+    }
+
+    #[test]
+    fn removed_viewers_stop_revealing() {
+        let mut app = headless_app();
+        app.update();
+        let mut grids = app.world.query_filtered::<Entity, With<DefaultGrid>>();
+        let grid = grids.iter(&app.world).next().unwrap();
+        let spawn_viewer = |app: &mut App| {
+            app.world
+                .spawn()
+                .insert(OnTile(HexCoord::ZERO))
+                .insert(Faction(0))
+                .insert(Viewer::default())
+                .insert(OnGrid(grid))
+                .id()
+        };
+        let state = |app: &App| {
+            app.world
+                .get::<Fog>(grid)
+                .unwrap()
+                .state(Faction(0), HexCoord::ZERO)
+        };
+
+        let unit = spawn_viewer(&mut app);
+        app.update();
+        assert_eq!(state(&app), TileVisibility::Visible);
+        app.world.despawn(unit);
+        app.update();
+        assert_eq!(state(&app), TileVisibility::Explored);
+
+        let unit = spawn_viewer(&mut app);
+        app.update();
+        assert_eq!(state(&app), TileVisibility::Visible);
+        app.world.entity_mut(unit).remove::<Viewer>();
+        app.update();
+        assert_eq!(state(&app), TileVisibility::Explored);
+    }
+
+    #[test]
+    fn viewers_only_reveal_their_grid() {
+        let mut app = headless_app();
+        app.update();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        let arena = spawn_grid(&mut commands, GridSettings::default(), Grid::default());
+        queue.apply(&mut app.world);
+        app.update();
+
+        let mut grids = app.world.query_filtered::<Entity, With<DefaultGrid>>();
+        let grid = grids.iter(&app.world).next().unwrap();
+        app.world
+            .spawn()
+            .insert(OnTile(HexCoord::ZERO))
+            .insert(Faction(0))
+            .insert(Viewer::default())
+            .insert(OnGrid(grid));
+        app.update();
+
+        let state = |app: &App, grid| {
+            app.world
+                .get::<Fog>(grid)
+                .unwrap()
+                .state(Faction(0), HexCoord::ZERO)
+        };
+        assert_eq!(state(&app, grid), TileVisibility::Visible);
+        assert_eq!(state(&app, arena), TileVisibility::Hidden);
+    }
+}
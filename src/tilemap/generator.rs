@@ -0,0 +1,413 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::{
+    erode, Climate, ClimateSettings, ErosionSettings, Grid, GridKind, GridSettings, Heightmap,
+    HeightmapSettings, HexCoord, HexEdge, HexVertex, Terrain,
+};
+
+/// How the terrain of the map is generated from its seed.
+///
+/// Changing this resource at runtime regenerates every tile.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeneratorSettings {
+    pub seed: u64,
+    /// Fraction of the highest elevation below which tiles are under water.
+    pub sea_level: f32,
+    /// Height of beaches above the sea, as a fraction of the highest elevation.
+    pub beach_height: f32,
+    /// Fraction of the highest elevation above which tiles are mountains.
+    pub mountain_level: f32,
+    /// Biomes of the land between the beaches and the mountains.
+    pub climate: ClimateSettings,
+    /// Chance for a grass tile to be a forest where forests grow best.
+    pub forest_density: f32,
+    /// Number of rivers on hex grids.
+    pub rivers: usize,
+    /// Fraction of the highest elevation above which rivers may spring.
+    pub river_source_level: f32,
+    /// Erosion of the heightmap before the terrain is assigned, if any.
+    pub erosion: Option<ErosionSettings>,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            sea_level: 0.3,
+            beach_height: 0.06,
+            mountain_level: 0.8,
+            climate: ClimateSettings::default(),
+            forest_density: 0.6,
+            rivers: 4,
+            river_source_level: 0.6,
+            erosion: None,
+        }
+    }
+}
+
+/// Reproducible terrain: the same settings always give the same tiles.
+pub struct MapGenerator {
+    settings: GeneratorSettings,
+    max_elevation: f32,
+    elevation: Heightmap,
+    /// Where forests grow, so that they come in clumps.
+    forests: Heightmap,
+    climate: Climate,
+    river_seed: u64,
+    erosion_seed: u64,
+}
+
+impl MapGenerator {
+    pub fn new(settings: &GeneratorSettings, heightmap: &HeightmapSettings) -> Self {
+        // ChaCha rather than `StdRng`, whose output may change between rand releases.
+        let mut rng = ChaCha8Rng::seed_from_u64(settings.seed);
+        let elevation = Heightmap::new(heightmap, rng.gen());
+        let forests = Heightmap::new(
+            &HeightmapSettings {
+                octaves: 2,
+                frequency: heightmap.frequency * 2.,
+                max_elevation: 1.,
+                ..heightmap.clone()
+            },
+            rng.gen(),
+        );
+        let climate = Climate::new(&settings.climate, heightmap, rng.gen(), rng.gen());
+        Self {
+            settings: settings.clone(),
+            max_elevation: heightmap.max_elevation,
+            elevation,
+            forests,
+            climate,
+            river_seed: rng.gen(),
+            erosion_seed: rng.gen(),
+        }
+    }
+
+    /// Whether the heightmap is eroded, which makes every tile depend on the
+    /// rest of the grid.
+    pub fn erodes(&self) -> bool {
+        self.settings.erosion.is_some()
+    }
+
+    /// Elevation of every tile of the grid, eroded if the settings ask for it.
+    ///
+    /// Without erosion, every tile only depends on the seed and its coordinates.
+    pub fn elevations(&self, grid_settings: &GridSettings) -> HashMap<HexCoord, f32> {
+        let mut elevations = grid_settings
+            .coords()
+            .into_iter()
+            .map(|coords| {
+                (
+                    coords,
+                    self.elevation.sample_wrapped(
+                        grid_settings.noise_pos(coords),
+                        grid_settings.noise_period(),
+                    ),
+                )
+            })
+            .collect();
+        if let Some(erosion) = &self.settings.erosion {
+            erode(
+                erosion,
+                grid_settings,
+                self.max_elevation,
+                self.erosion_seed,
+                &mut elevations,
+            );
+        }
+        elevations
+    }
+
+    /// Final elevation and terrain of the tile at `coords`, from its elevation
+    /// in the heightmap.
+    pub fn tile(
+        &self,
+        grid_settings: &GridSettings,
+        coords: HexCoord,
+        elevation: f32,
+    ) -> (f32, Terrain) {
+        let pos = grid_settings.noise_pos(coords);
+        let period = grid_settings.noise_period();
+        let height = elevation / self.max_elevation;
+        let settings = &self.settings;
+        let terrain = if height < settings.sea_level {
+            Terrain::Water
+        } else if height < settings.sea_level + settings.beach_height {
+            Terrain::Sand
+        } else if height >= settings.mountain_level {
+            Terrain::Mountain
+        } else {
+            // Forests are scattered over the grass of every climate.
+            match self
+                .climate
+                .terrain(pos, period, height - settings.sea_level)
+            {
+                Terrain::Grass
+                    if self.tile_rng(coords).gen::<f32>()
+                        < settings.forest_density * self.forests.sample_wrapped(pos, period) =>
+                {
+                    Terrain::Forest
+                }
+                terrain => terrain,
+            }
+        };
+        if terrain == Terrain::Water {
+            // The sea is flat.
+            (settings.sea_level * self.max_elevation, terrain)
+        } else {
+            (elevation, terrain)
+        }
+    }
+
+    /// Elevation and terrain of every tile of the grid, and its rivers.
+    pub fn generate(&self, grid_settings: &GridSettings) -> Grid {
+        let mut grid = Grid::default();
+        for (coords, elevation) in self.elevations(grid_settings) {
+            let (elevation, terrain) = self.tile(grid_settings, coords, elevation);
+            grid.elevations.insert(coords, elevation);
+            grid.terrains.insert(coords, terrain);
+        }
+        grid.rivers = self.rivers(grid_settings, &grid);
+        grid
+    }
+
+    /// Rivers springing from high vertices of `grid` and flowing downhill
+    /// along tile edges, until they reach the sea, a basin or the border.
+    ///
+    /// Edges only exist between hex tiles, other grids have no rivers.
+    pub fn rivers(&self, grid_settings: &GridSettings, grid: &Grid) -> HashSet<HexEdge> {
+        let mut rivers = HashSet::new();
+        if !matches!(grid_settings.kind, GridKind::Hex(_)) {
+            return rivers;
+        }
+        // Tiles around a vertex, wrapped so that rivers cross the seams.
+        let tiles = |vertex: HexVertex| vertex.tiles().map(|coords| grid_settings.wrap(coords));
+        // The mean elevation of the tiles around a vertex, if they are all on the grid.
+        let height = |vertex: HexVertex| {
+            let tiles = tiles(vertex);
+            if !tiles.iter().all(|coords| grid.contains(*coords)) {
+                return None;
+            }
+            Some(
+                tiles
+                    .iter()
+                    .map(|coords| grid.elevation(*coords))
+                    .sum::<f32>()
+                    / 3.,
+            )
+        };
+        let at_sea = |vertex: HexVertex| {
+            tiles(vertex)
+                .iter()
+                .any(|coords| grid.terrain(*coords) == Some(Terrain::Water))
+        };
+
+        // Sorted, so that sources are picked the same way every time.
+        let source_height = self.settings.river_source_level * self.max_elevation;
+        let sources: Vec<HexVertex> = grid
+            .terrains
+            .keys()
+            .flat_map(|coords| (0..6).map(move |corner| HexVertex::new(*coords, corner)))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter(|vertex| {
+                !at_sea(*vertex) && height(*vertex).is_some_and(|h| h >= source_height)
+            })
+            .collect();
+        let mut rng = ChaCha8Rng::seed_from_u64(self.river_seed);
+        for source in sources.choose_multiple(&mut rng, self.settings.rivers) {
+            let mut vertex = *source;
+            let mut vertex_height = height(vertex).unwrap();
+            while !at_sea(vertex) {
+                let lowest = vertex
+                    .neighbors()
+                    .iter()
+                    .filter_map(|next| Some((*next, height(*next)?)))
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                match lowest {
+                    Some((next, next_height)) if next_height < vertex_height => {
+                        rivers.insert(grid_settings.wrap_edge(vertex.edge_to(next).unwrap()));
+                        vertex = next;
+                        vertex_height = next_height;
+                    }
+                    _ => break,
+                }
+            }
+        }
+        rivers
+    }
+
+    /// Random numbers of a single tile.
+    fn tile_rng(&self, coords: HexCoord) -> ChaCha8Rng {
+        let tile = ((coords.q as u32 as u64) << 32) | coords.r as u32 as u64;
+        ChaCha8Rng::seed_from_u64(self.settings.seed ^ tile.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::BiomeTable;
+
+    fn terrain_map(grid_settings: &GridSettings, grid: &Grid) -> String {
+        grid_settings
+            .coords()
+            .into_iter()
+            .map(|coords| match grid.terrains[&coords] {
+                Terrain::Grass => 'g',
+                Terrain::Forest => 'f',
+                Terrain::Water => 'w',
+                Terrain::Mountain => 'm',
+                Terrain::Sand => 's',
+                Terrain::Snow => 'i',
+                Terrain::Desert => 'd',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_map() {
+        let grid_settings = GridSettings::default();
+        let heightmap = HeightmapSettings::default();
+        let settings = GeneratorSettings {
+            seed: 7,
+            ..Default::default()
+        };
+        let a = MapGenerator::new(&settings, &heightmap).generate(&grid_settings);
+        let b = MapGenerator::new(&settings, &heightmap).generate(&grid_settings);
+        assert_eq!(a.terrains, b.terrains);
+        assert_eq!(a.elevations, b.elevations);
+
+        let other = MapGenerator::new(
+            &GeneratorSettings {
+                seed: 8,
+                ..settings.clone()
+            },
+            &heightmap,
+        )
+        .generate(&grid_settings);
+        assert_ne!(a.elevations, other.elevations);
+
+        // Without erosion, tiles don't depend on the rest of the grid.
+        let generator = MapGenerator::new(&settings, &heightmap);
+        let small = GridSettings {
+            radius: 2,
+            ..grid_settings.clone()
+        };
+        for (coords, elevation) in generator.elevations(&small) {
+            let (elevation, terrain) = generator.tile(&small, coords, elevation);
+            assert_eq!(elevation, a.elevations[&coords]);
+            assert_eq!(terrain, a.terrains[&coords]);
+        }
+    }
+
+    #[test]
+    fn erosion_is_optional_and_seeded() {
+        let grid_settings = GridSettings::default();
+        let heightmap = HeightmapSettings::default();
+        let settings = GeneratorSettings {
+            seed: 7,
+            erosion: Some(ErosionSettings::default()),
+            ..Default::default()
+        };
+        let eroded = MapGenerator::new(&settings, &heightmap).generate(&grid_settings);
+        let again = MapGenerator::new(&settings, &heightmap).generate(&grid_settings);
+        assert_eq!(eroded.elevations, again.elevations);
+        assert_eq!(eroded.terrains, again.terrains);
+
+        let raw = MapGenerator::new(
+            &GeneratorSettings {
+                erosion: None,
+                ..settings.clone()
+            },
+            &heightmap,
+        )
+        .generate(&grid_settings);
+        assert_ne!(eroded.elevations, raw.elevations);
+    }
+
+    #[test]
+    fn rivers_flow_downhill() {
+        let grid_settings = GridSettings::default();
+        let settings = GeneratorSettings {
+            seed: 3,
+            rivers: 6,
+            ..Default::default()
+        };
+        let grid =
+            MapGenerator::new(&settings, &HeightmapSettings::default()).generate(&grid_settings);
+        assert!(!grid.rivers.is_empty());
+        let height = |vertex: HexVertex| {
+            vertex
+                .tiles()
+                .iter()
+                .map(|coords| grid.elevation(*coords))
+                .sum::<f32>()
+        };
+        for edge in grid.rivers.iter() {
+            let [a, b] = edge.vertices();
+            assert_ne!(height(a), height(b));
+            assert!(edge.tiles().iter().all(|coords| grid.contains(*coords)));
+        }
+
+        let square = GridSettings {
+            kind: GridKind::Square(crate::tilemap::Connectivity::Four),
+            ..Default::default()
+        };
+        let grid = MapGenerator::new(&settings, &HeightmapSettings::default()).generate(&square);
+        assert!(grid.rivers.is_empty());
+    }
+
+    #[test]
+    fn fixed_seed_output_is_locked() {
+        let grid_settings = GridSettings {
+            radius: 4,
+            ..Default::default()
+        };
+        // A single biome, so that only the elevation and the forests shape the map.
+        let settings = GeneratorSettings {
+            seed: 1234,
+            climate: ClimateSettings {
+                biomes: BiomeTable {
+                    biomes: Vec::new(),
+                    fallback: Terrain::Grass,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let grid =
+            MapGenerator::new(&settings, &HeightmapSettings::default()).generate(&grid_settings);
+        assert_eq!(
+            terrain_map(&grid_settings, &grid),
+            "gfwffgfgswwsggffgfffwfswwfsggfgfgfgggsssgffggswgffgfffffgsggg"
+        );
+        let elevation = |q, r| grid.elevations[&HexCoord::new(q, r)];
+        assert_eq!(elevation(0, 0), 0.49237686);
+        assert_eq!(elevation(1, 1), 0.39136425);
+        assert_eq!(elevation(-2, 4), 0.3987776);
+        // Under water, flattened to the sea level.
+        assert_eq!(elevation(3, -1), 0.3);
+    }
+
+    #[test]
+    fn fixed_seed_biomes_are_locked() {
+        let grid_settings = GridSettings {
+            radius: 4,
+            ..Default::default()
+        };
+        let settings = GeneratorSettings {
+            seed: 1234,
+            ..Default::default()
+        };
+        let grid =
+            MapGenerator::new(&settings, &HeightmapSettings::default()).generate(&grid_settings);
+        assert_eq!(
+            terrain_map(&grid_settings, &grid),
+            "gfwffgfgswwsggffgfffwfswwfsggfffffgffsssgffggswgfffffffffsfff"
+        );
+    }
+}
@@ -1,132 +1,926 @@
-use std::{collections::HashMap, f32::consts::PI};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    f32::consts::{FRAC_PI_4, SQRT_2},
+};
 
-use bevy::prelude::*;
-use bevy_ext::{raycast::RayHitable, shape::Cylinder};
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use bevy_ext::{
+    camera::PanOrbitCamera,
+    raycast::RayHitable,
+    shape::{Cylinder, Line},
+};
+use serde::{Deserialize, Serialize};
 
+use super::{
+    bake_chunk, Chunk, ChunkCoord, ChunkSettings, Connectivity, Fog, FogSettings,
+    GeneratorSettings, HeightmapSettings, HexCoord, HexEdge, HexOrientation, Layout, MapGenerator,
+    NoisePeriod, Occupancy, SpawnPoint, SquareCoord, Terrain, TerrainMaterials, Tile,
+    TileHighlight,
+};
 use crate::GridRayLayer;
 
-pub fn get_grid_pos(display_radius: u32, tile_size: f32) -> Vec<(Vec3, IVec2)> {
-    let mut positions = Vec::new();
-    let tile_size = tile_size + 0.1;
-    let angle = PI / 3.;
-    positions.push((Vec3::ZERO, IVec2::ZERO));
-    let grid_dirs = [
-        IVec2::X,
-        IVec2::Y,
-        IVec2::Y - IVec2::X,
-        -IVec2::X,
-        -IVec2::Y,
-        IVec2::X - IVec2::Y,
-    ];
-    for dir in 0..6 {
-        let grid_dir = grid_dirs[dir];
-        let o_grid_dir = grid_dirs[(dir + 2) % 6];
-        let a = angle + PI / 6. + dir as f32 * angle;
-        for i in 1..display_radius {
-            let dir_x = a.cos() * tile_size * i as f32;
-            let dir_z = a.sin() * tile_size * i as f32;
-            for j in 1..(i + 1) {
-                let shift_x = (a + 2. * angle).cos() * tile_size * (j - 1) as f32;
-                let shift_z = (a + 2. * angle).sin() * tile_size * (j - 1) as f32;
-                let x = dir_x + shift_x;
-                let z = dir_z + shift_z;
-                positions.push((
-                    Vec3::new(x, 0f32, z),
-                    i as i32 * grid_dir + (i + j) as i32 * o_grid_dir,
-                ));
+/// Height of a tile mesh at zero elevation.
+pub const TILE_HEIGHT: f32 = 0.25;
+
+#[derive(Component, Default)]
+pub struct Grid {
+    /// Tile entities of the spawned chunks.
+    pub tiles: HashMap<HexCoord, Entity>,
+    /// Spawned chunks.
+    pub chunks: HashMap<ChunkCoord, Entity>,
+    /// Height of every tile above the base tile height, sampled from the heightmap.
+    pub elevations: HashMap<HexCoord, f32>,
+    pub terrains: HashMap<HexCoord, Terrain>,
+    /// Free-form properties attached to tiles by map files.
+    pub properties: HashMap<HexCoord, BTreeMap<String, String>>,
+    pub spawn_points: Vec<SpawnPoint>,
+    /// Edges rivers flow along.
+    pub rivers: HashSet<HexEdge>,
+    /// Edges crossed by a road between the centers of their tiles.
+    pub roads: HashSet<HexEdge>,
+}
+
+impl Grid {
+    /// Whether the grid holds a tile at `coords`, whether or not it is spawned.
+    pub fn contains(&self, coords: HexCoord) -> bool {
+        self.terrains.contains_key(&coords)
+    }
+
+    pub fn elevation(&self, coords: HexCoord) -> f32 {
+        self.elevations.get(&coords).copied().unwrap_or(0.)
+    }
+
+    pub fn terrain(&self, coords: HexCoord) -> Option<Terrain> {
+        self.terrains.get(&coords).copied()
+    }
+
+    /// Whether a river runs between two adjacent tiles.
+    pub fn has_river(&self, settings: &GridSettings, a: HexCoord, b: HexCoord) -> bool {
+        settings
+            .edge_between(a, b)
+            .is_some_and(|edge| self.rivers.contains(&edge))
+    }
+
+    /// Whether a road joins two adjacent tiles.
+    pub fn has_road(&self, settings: &GridSettings, a: HexCoord, b: HexCoord) -> bool {
+        settings
+            .edge_between(a, b)
+            .is_some_and(|edge| self.roads.contains(&edge))
+    }
+
+    /// Join two adjacent tiles of the grid by a road. Returns whether they
+    /// could be joined.
+    pub fn add_road(&mut self, settings: &GridSettings, a: HexCoord, b: HexCoord) -> bool {
+        match settings.edge_between(a, b) {
+            Some(edge) if self.contains(settings.wrap(a)) && self.contains(settings.wrap(b)) => {
+                self.roads.insert(edge);
+                true
             }
+            _ => false,
         }
     }
-    positions
-}
 
-#[derive(Component)]
-struct Tile;
-
-#[derive(Component)]
-struct Grid {
-    pub tiles: HashMap<IVec2, Entity>,
+    /// Join every step of `tiles`, such as the tiles of a `Path`, by roads.
+    /// Returns whether every step could be joined.
+    pub fn add_road_along(&mut self, settings: &GridSettings, tiles: &[HexCoord]) -> bool {
+        let mut joined = true;
+        for step in tiles.windows(2) {
+            joined &= self.add_road(settings, step[0], step[1]);
+        }
+        joined
+    }
 }
 
-struct GridSettings {
+/// Shape of a grid, a component of every grid entity. Changing it at runtime
+/// reshapes that grid in place. The resource shapes the `DefaultGrid`, and
+/// changes to it are copied to that grid.
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GridSettings {
     pub radius: usize,
+    /// Distance from the center of a tile to its corners.
     pub tile_size: f32,
     pub kind: GridKind,
+    /// Wrapping maps are rectangles `2 * radius + 2` tiles a side instead,
+    /// so that hex columns and rows pair up across the seams.
+    #[serde(default)]
+    pub wrap: WrapMode,
 }
 
-enum GridKind {
-    Hex,
-    Square,
-    Iso,
+/// Which edges of the map continue on the opposite edge.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WrapMode {
+    #[default]
+    None,
+    /// The east and west edges meet.
+    Cylinder,
+    /// The east and west edges meet, and so do the north and south edges.
+    Torus,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GridKind {
+    Hex(HexOrientation),
+    Square(Connectivity),
+    /// Square tiles drawn as 2:1 diamonds.
+    Iso(Connectivity),
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self {
+            radius: 5,
+            tile_size: 0.55,
+            kind: GridKind::Hex(HexOrientation::Flat),
+            wrap: WrapMode::None,
+        }
+    }
 }
 
 impl GridSettings {
-    pub fn get_global_pos(&self, coords: IVec2) -> Vec3 {
+    pub fn layout(&self) -> Layout {
         match self.kind {
-            GridKind::Hex => self.get_global_pos_hex(coords),
-            GridKind::Square => {
-                unimplemented!()
+            GridKind::Hex(orientation) => Layout::hex(self.tile_size, orientation),
+            GridKind::Square(_) => Layout::square(self.tile_size * SQRT_2),
+            GridKind::Iso(_) => Layout::iso(self.tile_size),
+        }
+    }
+
+    /// Every tile coordinate of the grid, from the center outwards, or row by
+    /// row on wrapping maps.
+    pub fn coords(&self) -> Vec<HexCoord> {
+        if self.wrap != WrapMode::None {
+            let (low, high) = self.wrap_bounds();
+            return (low..=high)
+                .flat_map(|row| (low..=high).map(move |col| (col, row)))
+                .map(|(col, row)| self.offset_coords(col, row))
+                .collect();
+        }
+        match self.kind {
+            GridKind::Hex(_) => HexCoord::ZERO.spiral(self.radius as u32),
+            GridKind::Square(_) | GridKind::Iso(_) => SquareCoord::ZERO
+                .spiral(self.radius as u32)
+                .into_iter()
+                .map(HexCoord::from)
+                .collect(),
+        }
+    }
+
+    /// Coordinates adjacent to `coords`, wrapped around the seams, whether or
+    /// not the grid holds them.
+    pub fn neighbors(&self, coords: HexCoord) -> Vec<HexCoord> {
+        let neighbors = match self.kind {
+            GridKind::Hex(_) => coords.neighbors().to_vec(),
+            GridKind::Square(connectivity) | GridKind::Iso(connectivity) => {
+                SquareCoord::from(coords)
+                    .neighbors(connectivity)
+                    .into_iter()
+                    .map(HexCoord::from)
+                    .collect()
             }
-            GridKind::Iso => {
-                unimplemented!();
+        };
+        neighbors
+            .into_iter()
+            .map(|neighbor| self.wrap(neighbor))
+            .collect()
+    }
+
+    /// Number of steps between two tiles, the shortest way around on wrapping maps.
+    pub fn distance(&self, a: HexCoord, b: HexCoord) -> i32 {
+        let a = self.wrap(a);
+        self.images(self.wrap(b))
+            .into_iter()
+            .map(|b| self.steps(a, b))
+            .min()
+            .unwrap()
+    }
+
+    /// The copy of `to` the fewest steps away from the grid's own copy of
+    /// `from`, which is `to` itself on maps that don't wrap.
+    pub fn nearest_copy(&self, from: HexCoord, to: HexCoord) -> HexCoord {
+        let from = self.wrap(from);
+        self.images(self.wrap(to))
+            .into_iter()
+            .min_by_key(|to| self.steps(from, *to))
+            .unwrap()
+    }
+
+    /// Number of steps between two tiles, without going around the seams.
+    fn steps(&self, a: HexCoord, b: HexCoord) -> i32 {
+        match self.kind {
+            GridKind::Hex(_) => a.distance(b),
+            GridKind::Square(connectivity) | GridKind::Iso(connectivity) => {
+                SquareCoord::from(a).distance(SquareCoord::from(b), connectivity)
             }
         }
     }
 
-    fn get_global_pos_hex(&self, coords: IVec2) -> Vec3 {
-        let x_global = Vec3::new(2f32 * self.tile_size * 30f32.cos(), 0., 0.);
-        let y_global = Quat::from_euler(EulerRot::XYZ, 0., 0., 30.).mul_vec3(x_global);
-        return coords.x as f32 * x_global + coords.y as f32 * y_global;
+    /// The coordinates of the grid's own copy of `coords`, on wrapping maps.
+    pub fn wrap(&self, coords: HexCoord) -> HexCoord {
+        if self.wrap == WrapMode::None {
+            return coords;
+        }
+        let (low, high) = self.wrap_bounds();
+        let side = high - low + 1;
+        let (mut col, mut row) = self.offset_of(coords);
+        col = (col - low).rem_euclid(side) + low;
+        if self.wrap == WrapMode::Torus {
+            row = (row - low).rem_euclid(side) + low;
+        }
+        self.offset_coords(col, row)
+    }
+
+    /// Offsets from a tile to its next copies along every wrapping direction.
+    pub fn wrap_periods(&self) -> Vec<HexCoord> {
+        let (low, high) = self.wrap_bounds();
+        let side = high - low + 1;
+        match self.wrap {
+            WrapMode::None => Vec::new(),
+            WrapMode::Cylinder => vec![self.offset_coords(side, 0)],
+            WrapMode::Torus => vec![self.offset_coords(side, 0), self.offset_coords(0, side)],
+        }
+    }
+
+    /// Translation bringing `pos` to its copy nearest to `focus` on wrapping
+    /// maps, both given relative to the grid.
+    pub fn wrap_offset(&self, pos: Vec3, focus: Vec3) -> Vec3 {
+        let mut offset = Vec3::ZERO;
+        for period in self.wrap_periods() {
+            let period = self.get_global_pos(period);
+            let copies = (focus - pos - offset).dot(period) / period.length_squared();
+            offset += copies.round() * period;
+        }
+        offset
+    }
+
+    /// Edge between two adjacent tiles. On wrapping maps, tiles across a seam
+    /// are adjacent too, and the edge is stored from the grid's own copy of
+    /// its tile. Edges only exist between hex tiles, so other grids have no
+    /// rivers or roads.
+    pub fn edge_between(&self, a: HexCoord, b: HexCoord) -> Option<HexEdge> {
+        if !matches!(self.kind, GridKind::Hex(_)) {
+            return None;
+        }
+        let a = self.wrap(a);
+        let edge = self
+            .images(self.wrap(b))
+            .into_iter()
+            .find_map(|b| HexEdge::between(a, b))?;
+        Some(self.wrap_edge(edge))
+    }
+
+    /// The grid's own copy of `edge`, on wrapping maps.
+    pub fn wrap_edge(&self, edge: HexEdge) -> HexEdge {
+        HexEdge {
+            coords: self.wrap(edge.coords),
+            ..edge
+        }
+    }
+
+    /// `coords` and its copies one period away, on wrapping maps.
+    fn images(&self, coords: HexCoord) -> Vec<HexCoord> {
+        let mut images = vec![coords];
+        for period in self.wrap_periods() {
+            images = images
+                .into_iter()
+                .flat_map(|image| [image - period, image, image + period])
+                .collect();
+        }
+        images
+    }
+
+    /// Lowest and highest column and row of a wrapping map.
+    fn wrap_bounds(&self) -> (i32, i32) {
+        (-(self.radius as i32), self.radius as i32 + 1)
+    }
+
+    /// Column and row of a tile, which run along X and Z.
+    fn offset_of(&self, coords: HexCoord) -> (i32, i32) {
+        match self.kind {
+            // Every other column of flat hexes is shifted half a tile towards +Z.
+            GridKind::Hex(HexOrientation::Flat) => (coords.q, coords.r + coords.q.div_euclid(2)),
+            GridKind::Hex(HexOrientation::Pointy) => (coords.q + coords.r.div_euclid(2), coords.r),
+            GridKind::Square(_) | GridKind::Iso(_) => (coords.q, coords.r),
+        }
+    }
+
+    fn offset_coords(&self, col: i32, row: i32) -> HexCoord {
+        match self.kind {
+            GridKind::Hex(HexOrientation::Flat) => HexCoord::new(col, row - col.div_euclid(2)),
+            GridKind::Hex(HexOrientation::Pointy) => HexCoord::new(col - row.div_euclid(2), row),
+            GridKind::Square(_) | GridKind::Iso(_) => HexCoord::new(col, row),
+        }
+    }
+
+    /// Mesh of a single tile, slightly smaller than its cell to leave a gap.
+    pub fn tile_mesh(&self) -> Mesh {
+        let segments = match self.kind {
+            GridKind::Hex(_) => 6,
+            GridKind::Square(_) | GridKind::Iso(_) => 4,
+        };
+        Mesh::from(Cylinder {
+            height: TILE_HEIGHT,
+            radius: self.tile_size * 0.9,
+            segments,
+        })
+    }
+
+    /// Transform placing `tile_mesh` on the tile at `coords`, relative to the grid.
+    /// The mesh is stretched upwards so that its top sits `elevation` above `TILE_HEIGHT`.
+    pub fn tile_transform(&self, coords: HexCoord, elevation: f32) -> Transform {
+        let transform = Transform::from_translation(self.get_global_pos(coords))
+            .with_scale(Vec3::new(1., 1. + elevation / TILE_HEIGHT, 1.));
+        match self.kind {
+            // The cylinder starts its corners on +X, which is already a flat-top hexagon.
+            GridKind::Hex(orientation) => {
+                transform.with_rotation(Quat::from_rotation_y(-orientation.start_angle()))
+            }
+            GridKind::Square(_) => transform.with_rotation(Quat::from_rotation_y(FRAC_PI_4)),
+            // The 4 segment cylinder already has its corners on the axes, squash it into a diamond.
+            GridKind::Iso(_) => transform.with_scale(transform.scale * Vec3::new(1., 1., 0.5)),
+        }
+    }
+
+    pub fn get_global_pos(&self, coords: HexCoord) -> Vec3 {
+        self.layout().to_world(coords)
+    }
+
+    /// Where to sample the heightmap for a tile, in tile sizes so that the
+    /// terrain keeps its shape when `tile_size` changes.
+    pub fn noise_pos(&self, coords: HexCoord) -> Vec2 {
+        let pos = self.get_global_pos(coords) / self.tile_size;
+        Vec2::new(pos.x, pos.z)
+    }
+
+    /// Lengths after which the heightmap repeats, so that wrapping maps have
+    /// no seam. Iso maps wrap along the diagonals, which the noise can't follow.
+    pub fn noise_period(&self) -> NoisePeriod {
+        let mut noise_period = NoisePeriod::default();
+        for period in self.wrap_periods() {
+            let period = self.get_global_pos(period) / self.tile_size;
+            if period.z.abs() < 1e-4 {
+                noise_period.x = Some(period.x.abs());
+            } else if period.x.abs() < 1e-4 {
+                noise_period.z = Some(period.z.abs());
+            }
+        }
+        noise_period
+    }
+
+    /// Tile containing `pos`, given relative to the grid entity, wrapped
+    /// around the seams.
+    pub fn get_grid_pos(&self, pos: Vec3) -> HexCoord {
+        self.wrap(self.layout().to_coords(pos))
+    }
+
+    /// World position of a tile of the grid placed at `grid_transform`.
+    pub fn grid_to_world(&self, grid_transform: &GlobalTransform, coords: HexCoord) -> Vec3 {
+        grid_transform.mul_vec3(self.get_global_pos(coords))
+    }
+
+    /// Tile of the grid placed at `grid_transform` below the world point `pos`.
+    pub fn world_to_grid(&self, grid_transform: &GlobalTransform, pos: Vec3) -> HexCoord {
+        let local = grid_transform
+            .compute_matrix()
+            .inverse()
+            .transform_point3(pos);
+        self.get_grid_pos(local)
+    }
+
+    /// Tile where `ray` crosses the plane of the grid placed at `grid_transform`.
+    pub fn ray_to_grid(&self, grid_transform: &GlobalTransform, ray: &Line) -> Option<HexCoord> {
+        let local = ray.transform(grid_transform.compute_matrix().inverse());
+        let t = local.intersect_plane(Vec3::ZERO, Vec3::Y)?;
+        if t < 0. {
+            return None;
+        }
+        Some(self.get_grid_pos(local.point_at(t)))
+    }
+
+    /// Every tile of the grid along with its position relative to the grid.
+    pub fn tile_positions(&self) -> Vec<(Vec3, HexCoord)> {
+        let layout = self.layout();
+        self.coords()
+            .into_iter()
+            .map(|coords| (layout.to_world(coords), coords))
+            .collect()
     }
 }
 
-// pub fn maintaian(
-//     mut commands: Commands,
-//     mut meshes: ResMut<Assets<Mesh>>,
-//     mut materials: ResMut<Assets<StandardMaterial>>,
-//     mut grid_setting: ResMut<Assets<GridSettings>>,
-//     mut grids: Query<&mut Grid>,
-//     mut tiles: Query<(Entity, Tile, Transform, Visibility)>,
-// ) {
-//     for grid in grids.iter() {
-//         grid.tiles
-//     }
-// }
-
-pub fn create_grid(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+/// Marks the grid shaped by the `GridSettings` resource: the grid spawned at
+/// startup, or the last map loaded.
+#[derive(Component)]
+pub struct DefaultGrid;
+
+/// Marks the grids whose tiles come from the `MapGenerator`. They are
+/// generated again when the `HeightmapSettings` or the `GeneratorSettings`
+/// change, while the other grids, such as loaded maps, keep their tiles.
+#[derive(Component)]
+pub struct Generated;
+
+/// The grid a unit stands on.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OnGrid(pub Entity);
+
+/// The tile a unit stands on, on the grid named by its `OnGrid`.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OnTile(pub HexCoord);
+
+pub fn create_grid(mut commands: Commands, settings: Res<GridSettings>) {
+    let grid = spawn_grid(&mut commands, settings.clone(), Grid::default());
+    commands.entity(grid).insert(DefaultGrid).insert(Generated);
+}
+
+/// Copy the changes of the `GridSettings` resource to the `DefaultGrid`.
+pub fn apply_grid_settings(
+    settings: Res<GridSettings>,
+    mut grids: Query<&mut GridSettings, With<DefaultGrid>>,
 ) {
-    let mesh = meshes.add(Mesh::from(Cylinder {
-        height: 0.25,
-        radius: 0.5,
-        segments: 6,
-    }));
-    let material = materials.add(StandardMaterial::default());
-    let positions = get_grid_pos(6, 1f32 * (PI / 6f32).cos());
-    let mut childs = Vec::new();
-    let mut tiles = HashMap::new();
-    for (pos, gpos) in positions {
-        let tile = commands
-            .spawn_bundle(PbrBundle {
-                mesh: mesh.clone(),
-                material: material.clone(),
-                transform: Transform::from_translation(pos),
-                ..Default::default()
-            })
-            .insert(Tile)
-            .insert(RayHitable::<GridRayLayer>::new())
-            .id();
-        tiles.insert(gpos, tile);
-        childs.push(tile);
-    }
-    let grid_comp = Grid { tiles };
-    let grid = commands
+    if !settings.is_changed() {
+        return;
+    }
+    for mut grid_settings in grids.iter_mut() {
+        if *grid_settings != *settings {
+            *grid_settings = settings.clone();
+        }
+    }
+}
+
+/// Spawn a grid entity shaped by `settings`. `maintain_grid` fills in the
+/// tiles `grid` lacks and spawns its chunks. Insert another `Transform` to
+/// move the grid, and `Generated` to generate it again along with the
+/// generator settings.
+pub fn spawn_grid(commands: &mut Commands, settings: GridSettings, grid: Grid) -> Entity {
+    commands
         .spawn()
-        .insert(grid_comp)
+        .insert(settings)
+        .insert(grid)
+        .insert(Fog::default())
+        .insert(Occupancy::default())
+        .insert(TileHighlight::default())
         .insert(Transform::default())
         .insert(GlobalTransform::default())
-        .id();
-    commands.entity(grid).push_children(&childs);
+        .id()
+}
+
+/// Bring the tiles of every grid in line with its `GridSettings`, and spawn
+/// the chunks around the camera focus.
+///
+/// Tiles whose coordinates are still part of the grid are kept and moved,
+/// the others are despawned and the missing ones spawned. Merged chunks are
+/// baked again instead.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn maintain_grid(
+    mut commands: Commands,
+    heightmap_settings: Res<HeightmapSettings>,
+    generator_settings: Res<GeneratorSettings>,
+    chunk_settings: Res<ChunkSettings>,
+    fog_settings: Res<FogSettings>,
+    materials: Res<TerrainMaterials>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut tile_meshes: Local<HashMap<Entity, Handle<Mesh>>>,
+    mut focus_tiles: Local<HashMap<Entity, HexCoord>>,
+    cameras: Query<&PanOrbitCamera>,
+    mut grids: Query<(
+        Entity,
+        &mut Grid,
+        &GridSettings,
+        ChangeTrackers<GridSettings>,
+        &Fog,
+        &GlobalTransform,
+        Option<&Generated>,
+    )>,
+) {
+    // Forget the grids that were despawned.
+    tile_meshes.retain(|grid, _| grids.get(*grid).is_ok());
+    focus_tiles.retain(|grid, _| grids.get(*grid).is_ok());
+    let focus = cameras
+        .iter()
+        .next()
+        .map_or(Vec3::ZERO, |camera| camera.focus);
+    let chunk_size = chunk_settings.chunk_size;
+    // Also true the first time the system runs.
+    let generator_changed = heightmap_settings.is_changed() || generator_settings.is_changed();
+
+    for (grid_entity, mut grid, settings, settings_tracker, fog, grid_transform, generated) in
+        grids.iter_mut()
+    {
+        let regenerate = generator_changed && generated.is_some();
+        if settings_tracker.is_changed() || !tile_meshes.contains_key(&grid_entity) {
+            tile_meshes.insert(grid_entity, meshes.add(settings.tile_mesh()));
+        }
+        let mesh = tile_meshes[&grid_entity].clone();
+        let reshaped = settings_tracker.is_changed() || regenerate || grid.is_added();
+        if reshaped {
+            let generator = MapGenerator::new(&generator_settings, &heightmap_settings);
+            reshape_grid(
+                &mut commands,
+                settings,
+                &generator,
+                generated.is_some(),
+                regenerate,
+                &mut grid,
+            );
+            for (coords, tile) in grid.tiles.iter() {
+                commands
+                    .entity(*tile)
+                    .insert(settings.tile_transform(*coords, grid.elevation(*coords)))
+                    .insert(mesh.clone())
+                    .insert(materials.get(grid.terrains[coords]));
+            }
+        }
+        if chunk_settings.is_changed() || (reshaped && chunk_settings.merge_meshes) {
+            for (_, chunk) in grid.chunks.drain() {
+                commands.entity(chunk).despawn_recursive();
+            }
+            grid.tiles.clear();
+        }
+
+        let local_focus = grid_transform
+            .compute_matrix()
+            .inverse()
+            .transform_point3(focus);
+        // Not wrapped, so that crossing a seam moves the chunks to the copies near the focus.
+        let focus_tile = settings.layout().to_coords(local_focus);
+        if !reshaped
+            && !chunk_settings.is_changed()
+            && focus_tiles.get(&grid_entity) == Some(&focus_tile)
+        {
+            continue;
+        }
+        focus_tiles.insert(grid_entity, focus_tile);
+
+        let wanted = chunk_settings.chunks_around(settings, &grid, local_focus);
+        let unloaded: Vec<ChunkCoord> = grid
+            .chunks
+            .keys()
+            .filter(|chunk| !wanted.contains(chunk))
+            .copied()
+            .collect();
+        for chunk in unloaded {
+            let chunk_entity = grid.chunks.remove(&chunk).unwrap();
+            commands.entity(chunk_entity).despawn_recursive();
+            for coords in chunk.tiles(chunk_size) {
+                grid.tiles.remove(&coords);
+            }
+        }
+        for chunk in wanted {
+            // On wrapping maps, chunks are drawn at their copy nearest to the focus.
+            let transform = Transform::from_translation(
+                settings.wrap_offset(chunk.center(settings, chunk_size), local_focus),
+            );
+            if let Some(chunk_entity) = grid.chunks.get(&chunk) {
+                commands.entity(*chunk_entity).insert(transform);
+            }
+            if chunk_settings.merge_meshes {
+                if !grid.chunks.contains_key(&chunk) {
+                    let (chunk_mesh, mesh) = bake_chunk(
+                        settings,
+                        &grid,
+                        fog,
+                        fog_settings.faction,
+                        chunk,
+                        chunk_size,
+                    );
+                    let chunk_entity = commands
+                        .spawn_bundle(MaterialMeshBundle {
+                            mesh: meshes.add(mesh),
+                            material: materials.chunk(),
+                            transform,
+                            ..Default::default()
+                        })
+                        .insert(Chunk { coords: chunk })
+                        .insert(chunk_mesh)
+                        // The shadow pass expects the vertex layout of the PBR pipeline.
+                        .insert(NotShadowCaster)
+                        .insert(RayHitable::<GridRayLayer>::new())
+                        .id();
+                    commands.entity(grid_entity).push_children(&[chunk_entity]);
+                    grid.chunks.insert(chunk, chunk_entity);
+                }
+                continue;
+            }
+            let chunk_entity = match grid.chunks.get(&chunk) {
+                Some(chunk_entity) => *chunk_entity,
+                None => {
+                    let chunk_entity = commands
+                        .spawn()
+                        .insert(Chunk { coords: chunk })
+                        .insert(transform)
+                        .insert(GlobalTransform::default())
+                        .id();
+                    commands.entity(grid_entity).push_children(&[chunk_entity]);
+                    grid.chunks.insert(chunk, chunk_entity);
+                    chunk_entity
+                }
+            };
+            let mut childs = Vec::new();
+            for coords in chunk.tiles(chunk_size) {
+                if !grid.contains(coords) || grid.tiles.contains_key(&coords) {
+                    continue;
+                }
+                let transform = settings.tile_transform(coords, grid.elevation(coords));
+                let material = materials.get(grid.terrains[&coords]);
+                let tile = spawn_tile(&mut commands, coords, transform, &mesh, material);
+                grid.tiles.insert(coords, tile);
+                childs.push(tile);
+            }
+            commands.entity(chunk_entity).push_children(&childs);
+        }
+    }
+}
+
+/// Generate the tiles added to the grid and drop the removed ones. With
+/// `regenerate`, every tile is generated again. So is every tile of a
+/// `generated` grid the generator erodes, as eroded tiles depend on the whole
+/// grid. Rivers are generated again whenever tiles are, as they depend on the
+/// whole heightmap.
+fn reshape_grid(
+    commands: &mut Commands,
+    settings: &GridSettings,
+    generator: &MapGenerator,
+    generated: bool,
+    regenerate: bool,
+    grid: &mut Grid,
+) {
+    let wanted: HashSet<HexCoord> = settings.coords().into_iter().collect();
+    grid.tiles.retain(|coords, tile| {
+        let keep = wanted.contains(coords);
+        if !keep {
+            commands.entity(*tile).despawn_recursive();
+        }
+        keep
+    });
+    if regenerate {
+        grid.elevations.clear();
+        grid.terrains.clear();
+        grid.roads.clear();
+    }
+    grid.elevations.retain(|coords, _| wanted.contains(coords));
+    grid.terrains.retain(|coords, _| wanted.contains(coords));
+    grid.properties.retain(|coords, _| wanted.contains(coords));
+    grid.spawn_points
+        .retain(|spawn| wanted.contains(&spawn.coords));
+    let on_grid = |edge: &HexEdge| {
+        edge.tiles()
+            .iter()
+            .all(|coords| wanted.contains(&settings.wrap(*coords)))
+    };
+    grid.rivers.retain(on_grid);
+    grid.roads.retain(on_grid);
+    let missing: Vec<HexCoord> = wanted
+        .into_iter()
+        .filter(|coords| (generated && generator.erodes()) || !grid.contains(*coords))
+        .collect();
+    if missing.is_empty() {
+        return;
+    }
+    // Erosion needs the whole heightmap, so it is generated even for a few tiles.
+    let elevations = generator.elevations(settings);
+    for coords in missing {
+        let (elevation, terrain) = generator.tile(settings, coords, elevations[&coords]);
+        grid.elevations.insert(coords, elevation);
+        grid.terrains.insert(coords, terrain);
+    }
+    grid.rivers = generator.rivers(settings, grid);
+}
+
+fn spawn_tile(
+    commands: &mut Commands,
+    coords: HexCoord,
+    transform: Transform,
+    mesh: &Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+) -> Entity {
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: mesh.clone(),
+            material,
+            transform,
+            ..Default::default()
+        })
+        .insert(Tile { coords })
+        .insert(RayHitable::<GridRayLayer>::new())
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+    use crate::tilemap::tests::{grid_tiles, headless_app};
+
+    #[test]
+    fn spawned_tiles_agree_with_global_pos() {
+        let settings = GridSettings {
+            radius: 8,
+            tile_size: 0.55,
+            kind: GridKind::Hex(HexOrientation::Flat),
+            ..Default::default()
+        };
+        let positions = settings.tile_positions();
+        assert_eq!(positions.len(), 1 + 3 * 8 * 9);
+        for (pos, coords) in positions {
+            assert_eq!(pos, settings.get_global_pos(coords));
+            let frac = settings.layout().to_fractional(pos);
+            assert!((frac - Vec2::new(coords.q as f32, coords.r as f32)).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn square_and_iso_grid_tiles_round_trip() {
+        for kind in [
+            GridKind::Square(Connectivity::Four),
+            GridKind::Square(Connectivity::Eight),
+            GridKind::Iso(Connectivity::Four),
+            GridKind::Iso(Connectivity::Eight),
+        ] {
+            let settings = GridSettings {
+                radius: 6,
+                tile_size: 0.55,
+                kind,
+                ..Default::default()
+            };
+            let positions = settings.tile_positions();
+            assert_eq!(positions.len(), 13 * 13);
+            for (pos, coords) in positions {
+                assert_eq!(settings.get_grid_pos(pos), coords);
+                for n in settings.neighbors(coords) {
+                    assert_eq!(settings.distance(coords, n), 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn world_to_grid_follows_the_grid_transform() {
+        let settings = GridSettings {
+            radius: 4,
+            tile_size: 0.55,
+            kind: GridKind::Hex(HexOrientation::Flat),
+            ..Default::default()
+        };
+        let grid_transform = GlobalTransform {
+            translation: Vec3::new(10., 2., -3.),
+            rotation: Quat::from_rotation_y(0.7),
+            scale: Vec3::splat(2.),
+        };
+        for (_, coords) in settings.tile_positions() {
+            let world = settings.grid_to_world(&grid_transform, coords);
+            assert_eq!(settings.world_to_grid(&grid_transform, world), coords);
+            let ray = Line::new(world + Vec3::new(0.01, 5., 0.), -Vec3::Y);
+            assert_eq!(settings.ray_to_grid(&grid_transform, &ray), Some(coords));
+        }
+    }
+
+    #[test]
+    fn wrapping_grids_have_no_edges() {
+        for kind in [
+            GridKind::Hex(HexOrientation::Flat),
+            GridKind::Hex(HexOrientation::Pointy),
+            GridKind::Square(Connectivity::Four),
+            GridKind::Square(Connectivity::Eight),
+        ] {
+            for wrap in [WrapMode::Cylinder, WrapMode::Torus] {
+                let settings = GridSettings {
+                    radius: 3,
+                    kind,
+                    wrap,
+                    ..Default::default()
+                };
+                let coords = settings.coords();
+                assert_eq!(coords.len(), 8 * 8);
+                let on_grid: HashSet<HexCoord> = coords.iter().copied().collect();
+                for coords in &coords {
+                    assert_eq!(settings.wrap(*coords), *coords);
+                    for n in settings.neighbors(*coords) {
+                        assert_eq!(settings.distance(*coords, n), 1);
+                        assert_eq!(settings.distance(n, *coords), 1);
+                    }
+                    // A period away is the same tile, seen from anywhere.
+                    for period in settings.wrap_periods() {
+                        let copy = *coords + period;
+                        assert_eq!(settings.wrap(copy), *coords);
+                        assert_eq!(
+                            settings.get_grid_pos(settings.get_global_pos(copy)),
+                            *coords
+                        );
+                    }
+                }
+                // Every tile along the seams has all of its neighbors.
+                let full = match kind {
+                    GridKind::Hex(_) => 6,
+                    GridKind::Square(Connectivity::Four) => 4,
+                    _ => 8,
+                };
+                let inner = coords
+                    .iter()
+                    .filter(|coords| {
+                        settings
+                            .neighbors(**coords)
+                            .iter()
+                            .filter(|n| on_grid.contains(n))
+                            .count()
+                            == full
+                    })
+                    .count();
+                match wrap {
+                    WrapMode::Torus => assert_eq!(inner, coords.len()),
+                    _ => assert!(inner < coords.len()),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wrap_offset_picks_the_nearest_copy() {
+        let settings = GridSettings {
+            radius: 3,
+            wrap: WrapMode::Cylinder,
+            ..Default::default()
+        };
+        let period = settings.get_global_pos(settings.wrap_periods()[0]);
+        let west = settings.get_global_pos(HexCoord::new(-3, 1));
+        let east = settings.get_global_pos(HexCoord::new(4, -1));
+        assert_eq!(settings.wrap_offset(west, east), period);
+        assert_eq!(settings.wrap_offset(east, west), -period);
+        assert_eq!(settings.wrap_offset(west, Vec3::ZERO), Vec3::ZERO);
+        assert_eq!(GridSettings::default().wrap_offset(west, east), Vec3::ZERO);
+    }
+
+    #[test]
+    fn growing_an_eroded_grid_erodes_it_whole() {
+        let mut app = headless_app();
+        let generator_settings = GeneratorSettings {
+            erosion: Some(Default::default()),
+            ..Default::default()
+        };
+        app.insert_resource(generator_settings.clone());
+        app.update();
+        app.world.get_resource_mut::<GridSettings>().unwrap().radius = 7;
+        app.update();
+
+        let settings = app.world.get_resource::<GridSettings>().unwrap().clone();
+        let generated = MapGenerator::new(&generator_settings, &HeightmapSettings::default())
+            .generate(&settings);
+        let mut grids = app.world.query_filtered::<&Grid, With<DefaultGrid>>();
+        let grid = grids.iter(&app.world).next().unwrap();
+        assert_eq!(grid.elevations, generated.elevations);
+        assert_eq!(grid.terrains, generated.terrains);
+    }
+
+    #[test]
+    fn grids_keep_their_own_shape_and_place() {
+        let mut app = headless_app();
+        app.update();
+        let main_tiles = grid_tiles(&mut app);
+
+        let arena_settings = GridSettings {
+            radius: 2,
+            tile_size: 1.,
+            kind: GridKind::Square(Connectivity::Four),
+            ..Default::default()
+        };
+        let transform = Transform::from_xyz(6., 1., -3.)
+            .with_rotation(Quat::from_rotation_y(0.5))
+            .with_scale(Vec3::splat(2.));
+        let arena_transform = GlobalTransform::from(transform);
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        let arena = spawn_grid(&mut commands, arena_settings.clone(), Grid::default());
+        commands
+            .entity(arena)
+            .insert(transform)
+            .insert(arena_transform);
+        queue.apply(&mut app.world);
+        app.update();
+
+        let tiles = app.world.get::<Grid>(arena).unwrap().tiles.clone();
+        assert_eq!(tiles.len(), 5 * 5);
+        assert_eq!(grid_tiles(&mut app), main_tiles);
+        for (coords, tile) in tiles {
+            // Tiles are children of their chunk, which is a child of the grid.
+            let chunk = app.world.get::<Parent>(tile).unwrap().0;
+            let world = arena_transform
+                .mul_transform(*app.world.get::<Transform>(chunk).unwrap())
+                .mul_transform(*app.world.get::<Transform>(tile).unwrap())
+                .translation;
+            assert_eq!(
+                arena_settings.world_to_grid(&arena_transform, world),
+                coords
+            );
+        }
+
+        // Reshaping one grid leaves the other alone.
+        app.world.get_mut::<GridSettings>(arena).unwrap().radius = 1;
+        app.update();
+        assert_eq!(app.world.get::<Grid>(arena).unwrap().tiles.len(), 3 * 3);
+        assert_eq!(grid_tiles(&mut app), main_tiles);
+
+        // The resource only shapes the default grid.
+        app.world.get_resource_mut::<GridSettings>().unwrap().radius = 3;
+        app.update();
+        assert_eq!(app.world.get::<Grid>(arena).unwrap().tiles.len(), 3 * 3);
+        assert_eq!(grid_tiles(&mut app).len(), 1 + 3 * 3 * 4);
+    }
 }
@@ -0,0 +1,139 @@
+use std::f64::consts::TAU;
+
+use bevy::math::Vec2;
+use opensimplex_noise_rs::OpenSimplexNoise;
+
+/// Noise sampled for the elevation of every tile. The seed comes from the
+/// `GeneratorSettings`.
+///
+/// Changing this resource at runtime resamples the elevation of every tile.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeightmapSettings {
+    pub octaves: u32,
+    /// Frequency of the first octave, in cycles per tile size.
+    pub frequency: f64,
+    /// Amplitude factor from one octave to the next.
+    pub persistence: f64,
+    /// Frequency factor from one octave to the next.
+    pub lacunarity: f64,
+    /// Elevation of a tile where the noise peaks.
+    pub max_elevation: f32,
+}
+
+impl Default for HeightmapSettings {
+    fn default() -> Self {
+        Self {
+            octaves: 4,
+            frequency: 0.15,
+            persistence: 0.5,
+            lacunarity: 2.,
+            max_elevation: 1.,
+        }
+    }
+}
+
+/// Lengths, in tile sizes, after which the noise repeats along X and Z, so
+/// that wrapping maps have no seam.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NoisePeriod {
+    pub x: Option<f32>,
+    pub z: Option<f32>,
+}
+
+pub struct Heightmap {
+    noise: OpenSimplexNoise,
+    settings: HeightmapSettings,
+}
+
+impl Heightmap {
+    pub fn new(settings: &HeightmapSettings, seed: i64) -> Self {
+        Self {
+            noise: OpenSimplexNoise::new(Some(seed)),
+            settings: settings.clone(),
+        }
+    }
+
+    /// Elevation in `[0, max_elevation]` at `pos`, given in tile sizes.
+    pub fn sample(&self, pos: Vec2) -> f32 {
+        self.sample_wrapped(pos, NoisePeriod::default())
+    }
+
+    /// Like [`Heightmap::sample`], repeating every `period`.
+    ///
+    /// Wrapping axes are rolled into circles of the same length in a higher
+    /// dimension of the noise, so the terrain keeps its scale.
+    pub fn sample_wrapped(&self, pos: Vec2, period: NoisePeriod) -> f32 {
+        let circle = |coord: f32, period: Option<f32>| {
+            period.map(|period| {
+                let angle = coord as f64 / period as f64 * TAU;
+                let radius = period as f64 / TAU;
+                (radius * angle.cos(), radius * angle.sin())
+            })
+        };
+        let (x, z) = (pos.x as f64, pos.y as f64);
+        let (x_circle, z_circle) = (circle(pos.x, period.x), circle(pos.y, period.z));
+        let mut value = 0.;
+        let mut total = 0.;
+        let mut amplitude = 1.;
+        let mut frequency = self.settings.frequency;
+        for octave in 0..self.settings.octaves {
+            // Shift every octave so that they don't all share a zero at the origin.
+            let offset = octave as f64 * 17.3;
+            let at = |coord: f64| coord * frequency + offset;
+            let noise = match (x_circle, z_circle) {
+                (None, None) => self.noise.eval_2d(at(x), at(z)),
+                (Some((x1, x2)), None) => self.noise.eval_3d(at(x1), at(x2), at(z)),
+                (None, Some((z1, z2))) => self.noise.eval_3d(at(x), at(z1), at(z2)),
+                (Some((x1, x2)), Some((z1, z2))) => {
+                    self.noise.eval_4d(at(x1), at(x2), at(z1), at(z2))
+                }
+            };
+            value += amplitude * noise;
+            total += amplitude;
+            amplitude *= self.settings.persistence;
+            frequency *= self.settings.lacunarity;
+        }
+        if total <= 0. {
+            return 0.;
+        }
+        let normalized = ((value / total + 1.) / 2.).clamp(0., 1.);
+        normalized as f32 * self.settings.max_elevation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_seeded_and_bounded() {
+        let settings = HeightmapSettings::default();
+        let a = Heightmap::new(&settings, 0);
+        let b = Heightmap::new(&settings, 0);
+        let other = Heightmap::new(&settings, 42);
+        let mut differs = false;
+        for i in 0..200 {
+            let pos = Vec2::new(i as f32 * 0.37, i as f32 * -0.81);
+            let h = a.sample(pos);
+            assert_eq!(h, b.sample(pos));
+            assert!((0. ..=settings.max_elevation).contains(&h));
+            differs |= h != other.sample(pos);
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn wrapped_samples_repeat() {
+        let heightmap = Heightmap::new(&HeightmapSettings::default(), 3);
+        let period = NoisePeriod {
+            x: Some(18.),
+            z: Some(12.),
+        };
+        for i in 0..50 {
+            let pos = Vec2::new(i as f32 * 0.37, i as f32 * -0.81);
+            let h = heightmap.sample_wrapped(pos, period);
+            assert!((h - heightmap.sample_wrapped(pos + Vec2::new(18., 0.), period)).abs() < 1e-4);
+            assert!((h - heightmap.sample_wrapped(pos - Vec2::new(0., 12.), period)).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Axial coordinate of a tile on a hexagonal grid.
+///
+/// The third cube component `s` is implied by `q + r + s = 0` and is
+/// computed on demand with [`HexCoord::s`].
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct HexCoord {
+    pub q: i32,
+    pub r: i32,
+}
+
+impl HexCoord {
+    pub const ZERO: Self = Self::new(0, 0);
+
+    /// The six unit offsets, in counter-clockwise order.
+    /// Rotating by one step maps `DIRECTIONS[i]` onto `DIRECTIONS[i + 1]`.
+    pub const DIRECTIONS: [Self; 6] = [
+        Self::new(1, 0),
+        Self::new(0, 1),
+        Self::new(-1, 1),
+        Self::new(-1, 0),
+        Self::new(0, -1),
+        Self::new(1, -1),
+    ];
+
+    pub const fn new(q: i32, r: i32) -> Self {
+        Self { q, r }
+    }
+
+    pub fn from_cube(q: i32, r: i32, s: i32) -> Self {
+        debug_assert_eq!(q + r + s, 0, "cube coordinates must sum to zero");
+        Self::new(q, r)
+    }
+
+    /// Nearest tile to a fractional `(q, r)` position, using cube rounding.
+    pub fn round(frac: Vec2) -> Self {
+        let (q, r, s) = (frac.x, frac.y, -frac.x - frac.y);
+        let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+        let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+        if dq > dr && dq > ds {
+            rq = -rr - rs;
+        } else if dr > ds {
+            rr = -rq - rs;
+        }
+        Self::new(rq as i32, rr as i32)
+    }
+
+    pub fn s(self) -> i32 {
+        -self.q - self.r
+    }
+
+    pub fn neighbor(self, dir: usize) -> Self {
+        self + Self::DIRECTIONS[dir % 6]
+    }
+
+    pub fn neighbors(self) -> [Self; 6] {
+        Self::DIRECTIONS.map(|dir| self + dir)
+    }
+
+    /// Distance from the origin, in tiles.
+    pub fn length(self) -> i32 {
+        (self.q.abs() + self.r.abs() + self.s().abs()) / 2
+    }
+
+    pub fn distance(self, other: Self) -> i32 {
+        (self - other).length()
+    }
+
+    /// Every tile at exactly `radius` steps from `self`.
+    pub fn ring(self, radius: u32) -> Vec<Self> {
+        if radius == 0 {
+            return vec![self];
+        }
+        let mut tiles = Vec::with_capacity(6 * radius as usize);
+        let mut hex = self + Self::DIRECTIONS[4] * radius as i32;
+        for dir in 0..6 {
+            for _ in 0..radius {
+                tiles.push(hex);
+                hex = hex.neighbor(dir);
+            }
+        }
+        tiles
+    }
+
+    /// Every tile within `radius` steps of `self`, from the center outwards.
+    pub fn spiral(self, radius: u32) -> Vec<Self> {
+        let mut tiles = Vec::with_capacity(1 + 3 * (radius * (radius + 1)) as usize);
+        for r in 0..=radius {
+            tiles.extend(self.ring(r));
+        }
+        tiles
+    }
+
+    /// Tiles crossed by the straight segment from `self` to `other`, both ends included.
+    pub fn line_to(self, other: Self) -> Vec<Self> {
+        let n = self.distance(other);
+        if n == 0 {
+            return vec![self];
+        }
+        let delta = other - self;
+        // Nudge the segment off the edges between tiles so that rounding never hesitates.
+        let nudge = Vec2::new(1e-4, 2e-4);
+        (0..=n)
+            .map(|i| {
+                let t = i as f32 / n as f32;
+                let frac = Vec2::new(delta.q as f32, delta.r as f32) * t + nudge;
+                self + Self::round(frac)
+            })
+            .collect()
+    }
+
+    /// Rotate around the origin by `steps` sixths of a turn.
+    pub fn rotate(self, steps: i32) -> Self {
+        let (mut q, mut r, mut s) = (self.q, self.r, self.s());
+        for _ in 0..steps.rem_euclid(6) {
+            let (nq, nr, ns) = (-r, -s, -q);
+            q = nq;
+            r = nr;
+            s = ns;
+        }
+        Self::from_cube(q, r, s)
+    }
+
+    pub fn rotate_around(self, center: Self, steps: i32) -> Self {
+        (self - center).rotate(steps) + center
+    }
+
+    /// Mirror across the `q` axis, swapping `r` and `s`.
+    pub fn reflect_q(self) -> Self {
+        Self::from_cube(self.q, self.s(), self.r)
+    }
+
+    /// Mirror across the `r` axis, swapping `q` and `s`.
+    pub fn reflect_r(self) -> Self {
+        Self::from_cube(self.s(), self.r, self.q)
+    }
+
+    /// Mirror across the `s` axis, swapping `q` and `r`.
+    pub fn reflect_s(self) -> Self {
+        Self::from_cube(self.r, self.q, self.s())
+    }
+}
+
+impl Add for HexCoord {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.q + rhs.q, self.r + rhs.r)
+    }
+}
+
+impl AddAssign for HexCoord {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for HexCoord {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.q - rhs.q, self.r - rhs.r)
+    }
+}
+
+impl SubAssign for HexCoord {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for HexCoord {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.q, -self.r)
+    }
+}
+
+impl Mul<i32> for HexCoord {
+    type Output = Self;
+
+    fn mul(self, rhs: i32) -> Self {
+        Self::new(self.q * rhs, self.r * rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn coord() -> impl Strategy<Value = HexCoord> {
+        (-1000..1000, -1000..1000).prop_map(|(q, r)| HexCoord::new(q, r))
+    }
+
+    #[test]
+    fn directions_are_unit_and_rotate_into_each_other() {
+        for (i, dir) in HexCoord::DIRECTIONS.iter().enumerate() {
+            assert_eq!(dir.length(), 1);
+            assert_eq!(dir.rotate(1), HexCoord::DIRECTIONS[(i + 1) % 6]);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn cube_components_sum_to_zero(a in coord()) {
+            prop_assert_eq!(a.q + a.r + a.s(), 0);
+        }
+
+        #[test]
+        fn neighbors_are_at_distance_one(a in coord()) {
+            for n in a.neighbors() {
+                prop_assert_eq!(a.distance(n), 1);
+            }
+        }
+
+        #[test]
+        fn distance_is_a_metric(a in coord(), b in coord(), c in coord()) {
+            prop_assert_eq!(a.distance(a), 0);
+            prop_assert_eq!(a.distance(b), b.distance(a));
+            prop_assert!(a.distance(c) <= a.distance(b) + b.distance(c));
+        }
+
+        #[test]
+        fn ring_has_six_r_distinct_tiles_at_r(a in coord(), radius in 1u32..20) {
+            let ring = a.ring(radius);
+            prop_assert_eq!(ring.len(), 6 * radius as usize);
+            prop_assert_eq!(ring.iter().collect::<HashSet<_>>().len(), ring.len());
+            for hex in ring {
+                prop_assert_eq!(a.distance(hex), radius as i32);
+            }
+        }
+
+        #[test]
+        fn spiral_covers_the_whole_disk(a in coord(), radius in 0u32..20) {
+            let spiral = a.spiral(radius);
+            let n = 1 + 3 * radius * (radius + 1);
+            prop_assert_eq!(spiral.len(), n as usize);
+            prop_assert_eq!(spiral.iter().collect::<HashSet<_>>().len(), n as usize);
+            prop_assert!(spiral.iter().all(|hex| a.distance(*hex) <= radius as i32));
+        }
+
+        #[test]
+        fn rotation_preserves_distance_and_cycles(a in coord(), c in coord(), steps in -12i32..12) {
+            prop_assert_eq!(a.rotate_around(c, steps).distance(c), a.distance(c));
+            prop_assert_eq!(a.rotate(steps).rotate(-steps), a);
+            prop_assert_eq!(a.rotate(6), a);
+            prop_assert_eq!(a.rotate(3), -a);
+        }
+
+        #[test]
+        fn rounding_is_identity_on_tiles(a in coord(), dq in -0.2f32..0.2, dr in -0.2f32..0.2) {
+            prop_assert_eq!(HexCoord::round(Vec2::new(a.q as f32, a.r as f32)), a);
+            prop_assert_eq!(HexCoord::round(Vec2::new(a.q as f32 + dq, a.r as f32 + dr)), a);
+        }
+
+        #[test]
+        fn lines_are_connected_and_shortest(a in coord(), b in coord()) {
+            let b = a + HexCoord::new(b.q % 50, b.r % 50);
+            let line = a.line_to(b);
+            prop_assert_eq!(line.len() as i32, a.distance(b) + 1);
+            prop_assert_eq!(line[0], a);
+            prop_assert_eq!(*line.last().unwrap(), b);
+            for step in line.windows(2) {
+                prop_assert_eq!(step[0].distance(step[1]), 1);
+            }
+        }
+
+        #[test]
+        fn reflection_is_an_involution(a in coord()) {
+            prop_assert_eq!(a.reflect_q().reflect_q(), a);
+            prop_assert_eq!(a.reflect_r().reflect_r(), a);
+            prop_assert_eq!(a.reflect_s().reflect_s(), a);
+            prop_assert_eq!(a.reflect_q().length(), a.length());
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::TAU,
+};
+
+use bevy::{prelude::*, render::render_resource::PrimitiveTopology};
+
+use super::{
+    Chunk, ChunkCoord, ChunkSettings, Grid, GridKind, GridSettings, HexCoord, TILE_HEIGHT,
+};
+
+/// Height of highlights above the tiles, so that they don't flicker.
+const HIGHLIGHT_OFFSET: f32 = 0.02;
+
+/// Tiles of a grid drawn highlighted, such as the preview of an `AoeShape`.
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct TileHighlight {
+    pub tiles: HashSet<HexCoord>,
+}
+
+/// Marks the highlight meshes of a grid, which are children of its chunks.
+#[derive(Component)]
+pub struct HighlightMesh;
+
+pub struct HighlightMaterial(pub Handle<StandardMaterial>);
+
+impl FromWorld for HighlightMaterial {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world
+            .get_resource_mut::<Assets<StandardMaterial>>()
+            .expect("TileMapPlugin needs the StandardMaterial assets");
+        Self(materials.add(StandardMaterial {
+            base_color: Color::rgba(1., 0.85, 0.2, 0.5),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..Default::default()
+        }))
+    }
+}
+
+/// Flat caps over the tops of `tiles`, slightly smaller than the tiles.
+pub fn highlight_mesh(settings: &GridSettings, grid: &Grid, tiles: &HashSet<HexCoord>) -> Mesh {
+    let segments = match settings.kind {
+        GridKind::Hex(_) => 6,
+        GridKind::Square(_) | GridKind::Iso(_) => 4,
+    };
+    let angle = TAU / segments as f32;
+    let radius = settings.tile_size * 0.8;
+    // Corners of a tile mesh before `tile_transform`, starting on +X like its cylinder.
+    let corners: Vec<Vec3> = (0..segments)
+        .map(|i| {
+            let angle = angle * i as f32;
+            Vec3::new(radius * angle.cos(), TILE_HEIGHT, radius * angle.sin())
+        })
+        .collect();
+
+    let mut sorted: Vec<HexCoord> = tiles.iter().copied().collect();
+    sorted.sort_unstable();
+    let mut positions = Vec::with_capacity(sorted.len() * segments * 3);
+    for coords in sorted {
+        let matrix = settings
+            .tile_transform(coords, grid.elevation(coords))
+            .compute_matrix();
+        let lift = Vec3::Y * HIGHLIGHT_OFFSET;
+        let top = |corner: Vec3| matrix.transform_point3(corner) + lift;
+        let center = top(Vec3::Y * TILE_HEIGHT);
+        for i in 0..segments {
+            // Counter-clockwise seen from above, so that the caps face up.
+            for corner in [center, top(corners[(i + 1) % segments]), top(corners[i])] {
+                positions.push(corner.to_array());
+            }
+        }
+    }
+    let count = positions.len();
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 1., 0.]; count]);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0., 0.]; count]);
+    mesh
+}
+
+/// Rebuild the highlight meshes of the grids whose highlight or tiles changed.
+///
+/// Every spawned chunk gets the highlight of its own tiles, so that it moves
+/// along with the chunk on wrapping maps.
+#[allow(clippy::type_complexity)]
+pub fn draw_highlights(
+    mut commands: Commands,
+    material: Res<HighlightMaterial>,
+    chunk_settings: Res<ChunkSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    grids: Query<
+        (&Grid, &GridSettings, &TileHighlight),
+        Or<(Changed<TileHighlight>, Changed<Grid>, Changed<GridSettings>)>,
+    >,
+    chunks: Query<&Children, With<Chunk>>,
+    highlight_meshes: Query<(), With<HighlightMesh>>,
+) {
+    for (grid, settings, highlight) in grids.iter() {
+        for chunk in grid.chunks.values() {
+            for child in chunks
+                .get(*chunk)
+                .iter()
+                .flat_map(|children| children.iter())
+            {
+                if highlight_meshes.get(*child).is_ok() {
+                    commands.entity(*child).despawn_recursive();
+                }
+            }
+        }
+        let mut by_chunk: HashMap<ChunkCoord, HashSet<HexCoord>> = HashMap::new();
+        for coords in highlight.tiles.iter() {
+            let chunk = ChunkCoord::of(*coords, chunk_settings.chunk_size);
+            by_chunk.entry(chunk).or_default().insert(*coords);
+        }
+        for (chunk, tiles) in by_chunk {
+            let chunk_entity = match grid.chunks.get(&chunk) {
+                Some(chunk_entity) => *chunk_entity,
+                None => continue,
+            };
+            let mesh = commands
+                .spawn_bundle(PbrBundle {
+                    mesh: meshes.add(highlight_mesh(settings, grid, &tiles)),
+                    material: material.0.clone(),
+                    ..Default::default()
+                })
+                .insert(HighlightMesh)
+                .id();
+            commands.entity(chunk_entity).push_children(&[mesh]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::mesh::VertexAttributeValues;
+
+    use super::*;
+    use crate::tilemap::{tests::headless_app, AoeShape};
+
+    #[test]
+    fn aoe_previews_are_highlighted() {
+        let mut app = headless_app();
+        app.update();
+        let mut grids = app
+            .world
+            .query::<(&Grid, &GridSettings, &mut TileHighlight)>();
+        let (grid, settings, mut highlight) = grids.iter_mut(&mut app.world).next().unwrap();
+        let tiles = AoeShape::Cone(3).tiles(grid, settings, HexCoord::ZERO, 2);
+        assert_eq!(tiles.len(), 7);
+        highlight.tiles = tiles.iter().copied().collect();
+        app.update();
+
+        let mut highlight_meshes = app
+            .world
+            .query_filtered::<(&Parent, &Handle<Mesh>), With<HighlightMesh>>();
+        let mut grids = app.world.query::<(&Grid, &GridSettings)>();
+        let (grid, settings) = grids.iter(&app.world).next().unwrap();
+        let chunk_size = app
+            .world
+            .get_resource::<ChunkSettings>()
+            .unwrap()
+            .chunk_size;
+        let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+        let mut caps = 0;
+        for (parent, mesh) in highlight_meshes.iter(&app.world) {
+            let chunk = app.world.get::<Chunk>(parent.0).unwrap().coords;
+            let mesh = meshes.get(mesh).unwrap();
+            // Every cap sits just above the top of its tile, in the chunk of the tile.
+            let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+                Some(VertexAttributeValues::Float32x3(positions)) => positions,
+                _ => panic!("Highlight mesh without positions"),
+            };
+            for triangle in positions.chunks(3) {
+                let center = Vec3::from(triangle[0]);
+                let coords = settings.get_grid_pos(center);
+                assert!(tiles.contains(&coords));
+                assert_eq!(ChunkCoord::of(coords, chunk_size), chunk);
+                let top = TILE_HEIGHT + grid.elevation(coords) + HIGHLIGHT_OFFSET;
+                assert!((center.y - top).abs() < 1e-4);
+            }
+            caps += mesh.count_vertices();
+        }
+        assert_eq!(caps, 7 * 6 * 3);
+
+        let mut grids = app.world.query::<&mut TileHighlight>();
+        grids.iter_mut(&mut app.world).next().unwrap().tiles.clear();
+        app.update();
+        assert_eq!(highlight_meshes.iter(&app.world).count(), 0);
+    }
+}
@@ -0,0 +1,228 @@
+use std::f32::consts::{FRAC_PI_3, FRAC_PI_6};
+
+use bevy::math::{Mat2, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use super::HexCoord;
+
+/// Maps grid coordinates to positions on the grid's XZ plane and back.
+///
+/// Every position used to place or pick a tile must go through a `Layout`,
+/// so that spawning, picking and gameplay agree on where a tile is.
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    /// Columns are the world offsets of one step along `q` and along `r`.
+    forward: Mat2,
+    backward: Mat2,
+    cell: Cell,
+}
+
+/// Which way hexagons point along the Z axis of the grid.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HexOrientation {
+    /// Corners on the X axis, flat edges facing +Z and -Z.
+    #[default]
+    Flat,
+    /// Corners on the Z axis, flat edges facing +X and -X.
+    Pointy,
+}
+
+impl HexOrientation {
+    /// Angle around Y of the first corner of a tile, from the +X axis towards +Z.
+    pub fn start_angle(self) -> f32 {
+        match self {
+            HexOrientation::Flat => 0.,
+            HexOrientation::Pointy => FRAC_PI_6,
+        }
+    }
+
+    /// Angle around Y, from the +X axis towards +Z, of `HexCoord::DIRECTIONS[dir]`.
+    pub fn direction_angle(self, dir: usize) -> f32 {
+        let first = match self {
+            HexOrientation::Flat => FRAC_PI_6,
+            HexOrientation::Pointy => 0.,
+        };
+        first + FRAC_PI_3 * (dir % 6) as f32
+    }
+}
+
+/// Shape of a tile, which decides how a fractional position is rounded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Cell {
+    Hex,
+    Square,
+}
+
+impl Layout {
+    /// Hexagons with `size` from center to corner.
+    pub fn hex(size: f32, orientation: HexOrientation) -> Self {
+        let sqrt_3 = 3f32.sqrt();
+        let (q, r) = match orientation {
+            HexOrientation::Flat => (Vec2::new(1.5, sqrt_3 / 2.), Vec2::new(0., sqrt_3)),
+            HexOrientation::Pointy => (Vec2::new(sqrt_3, 0.), Vec2::new(sqrt_3 / 2., 1.5)),
+        };
+        Self::from_basis(Cell::Hex, q * size, r * size)
+    }
+
+    /// Axis aligned squares of side `size`, `q` along +X and `r` along +Z.
+    pub fn square(size: f32) -> Self {
+        Self::from_basis(Cell::Square, Vec2::X * size, Vec2::Y * size)
+    }
+
+    /// Diamonds `2 * size` wide along X and `size` deep along Z, with `q`
+    /// running towards +X+Z and `r` towards -X+Z.
+    ///
+    /// This is a square grid seen at an angle, so it rounds like one.
+    pub fn iso(size: f32) -> Self {
+        Self::from_basis(
+            Cell::Square,
+            Vec2::new(1., 0.5) * size,
+            Vec2::new(-1., 0.5) * size,
+        )
+    }
+
+    fn from_basis(cell: Cell, q: Vec2, r: Vec2) -> Self {
+        let forward = Mat2::from_cols(q, r);
+        Self {
+            forward,
+            backward: forward.inverse(),
+            cell,
+        }
+    }
+
+    pub fn to_world(self, coord: HexCoord) -> Vec3 {
+        let pos = self.forward * Vec2::new(coord.q as f32, coord.r as f32);
+        Vec3::new(pos.x, 0., pos.y)
+    }
+
+    /// Unit vector on the grid plane pointing from a tile towards `tile + offset`.
+    pub fn direction(self, offset: HexCoord) -> Vec3 {
+        self.to_world(offset).normalize_or_zero()
+    }
+
+    /// Unrounded `(q, r)` of the point of the grid plane below `pos`.
+    pub fn to_fractional(self, pos: Vec3) -> Vec2 {
+        self.backward * Vec2::new(pos.x, pos.z)
+    }
+
+    /// The tile containing the point of the grid plane below `pos`.
+    pub fn to_coords(self, pos: Vec3) -> HexCoord {
+        let frac = self.to_fractional(pos);
+        match self.cell {
+            Cell::Hex => HexCoord::round(frac),
+            // Tiles are centered on their coordinates, so shift by half a tile before flooring.
+            Cell::Square => {
+                let cell = (frac + Vec2::splat(0.5)).floor();
+                HexCoord::new(cell.x as i32, cell.y as i32)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIENTATIONS: [HexOrientation; 2] = [HexOrientation::Flat, HexOrientation::Pointy];
+
+    #[test]
+    fn hex_round_trip() {
+        for orientation in ORIENTATIONS {
+            let layout = Layout::hex(0.55, orientation);
+            for coord in HexCoord::ZERO.spiral(12) {
+                let frac = layout.to_fractional(layout.to_world(coord));
+                assert!(
+                    (frac.x - coord.q as f32).abs() < 1e-4,
+                    "{:?} -> {}",
+                    coord,
+                    frac
+                );
+                assert!(
+                    (frac.y - coord.r as f32).abs() < 1e-4,
+                    "{:?} -> {}",
+                    coord,
+                    frac
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hex_points_inside_a_tile_map_to_it() {
+        let size = 0.55;
+        // Stay just inside the inscribed circle of the hexagon.
+        let inner = size * 3f32.sqrt() / 2. * 0.99;
+        for orientation in ORIENTATIONS {
+            let layout = Layout::hex(size, orientation);
+            for coord in HexCoord::ZERO.spiral(8) {
+                let center = layout.to_world(coord);
+                for step in 0..24 {
+                    let angle = step as f32 * std::f32::consts::PI / 12.;
+                    let pos = center + Vec3::new(angle.cos(), 0., angle.sin()) * inner;
+                    assert_eq!(layout.to_coords(pos), coord);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn square_points_inside_a_tile_map_to_it() {
+        let layout = Layout::square(2.);
+        for coord in HexCoord::ZERO.spiral(5) {
+            let center = layout.to_world(coord);
+            for offset in [
+                Vec3::ZERO,
+                Vec3::new(0.99, 0., 0.99),
+                Vec3::new(-0.99, 0., -0.99),
+            ] {
+                assert_eq!(layout.to_coords(center + offset), coord);
+            }
+        }
+    }
+
+    #[test]
+    fn iso_points_inside_a_tile_map_to_it() {
+        let size = 0.55;
+        let layout = Layout::iso(size);
+        for coord in HexCoord::ZERO.spiral(5) {
+            let center = layout.to_world(coord);
+            assert_eq!(layout.to_coords(center), coord);
+            // Just inside each corner of the diamond.
+            for offset in [
+                Vec3::new(size * 0.95, 0., 0.),
+                Vec3::new(-size * 0.95, 0., 0.),
+                Vec3::new(0., 0., size * 0.45),
+                Vec3::new(0., 0., -size * 0.45),
+            ] {
+                assert_eq!(layout.to_coords(center + offset), coord);
+            }
+        }
+    }
+
+    #[test]
+    fn hex_neighbors_are_equidistant() {
+        for orientation in ORIENTATIONS {
+            let layout = Layout::hex(1., orientation);
+            let center = layout.to_world(HexCoord::new(3, -2));
+            for n in HexCoord::new(3, -2).neighbors() {
+                let d = layout.to_world(n).distance(center);
+                assert!((d - 3f32.sqrt()).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn hex_directions_face_the_edges() {
+        for orientation in ORIENTATIONS {
+            let layout = Layout::hex(1., orientation);
+            for (i, dir) in HexCoord::DIRECTIONS.iter().enumerate() {
+                let angle = orientation.direction_angle(i);
+                let expected = Vec3::new(angle.cos(), 0., angle.sin());
+                assert!(layout.direction(*dir).distance(expected) < 1e-4);
+                // Edge normals sit halfway between two corners.
+                let from_corner = (angle - orientation.start_angle()).rem_euclid(FRAC_PI_3);
+                assert!((from_corner - FRAC_PI_6).abs() < 1e-4);
+            }
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    spawn_grid, DefaultGrid, Faction, Fog, Generated, Grid, GridSettings, HexCoord, HexEdge,
+    Occupancy, OnGrid, Terrain, TileHighlight,
+};
+
+/// Version written in saved maps. Bump it when the format changes.
+pub const MAP_FORMAT_VERSION: u32 = 1;
+
+/// Where units of a faction enter the map.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpawnPoint {
+    pub coords: HexCoord,
+    pub faction: Faction,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TileRecord {
+    pub coords: HexCoord,
+    pub terrain: Terrain,
+    pub elevation: f32,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, String>,
+}
+
+/// A map as stored on disk, in RON.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MapFile {
+    pub version: u32,
+    pub settings: GridSettings,
+    /// Every tile of the grid, sorted by coordinates.
+    pub tiles: Vec<TileRecord>,
+    #[serde(default)]
+    pub spawn_points: Vec<SpawnPoint>,
+    /// Sorted, like tiles.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rivers: Vec<HexEdge>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roads: Vec<HexEdge>,
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Ron(ron::Error),
+    /// The file was written in a format this build can't read.
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(err) => write!(f, "map file I/O failed: {}", err),
+            MapError::Ron(err) => write!(f, "invalid map file: {}", err),
+            MapError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported map format version {}, expected {}",
+                version, MAP_FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<io::Error> for MapError {
+    fn from(err: io::Error) -> Self {
+        MapError::Io(err)
+    }
+}
+
+impl From<ron::Error> for MapError {
+    fn from(err: ron::Error) -> Self {
+        MapError::Ron(err)
+    }
+}
+
+impl MapFile {
+    pub fn from_grid(settings: &GridSettings, grid: &Grid) -> Self {
+        let mut tiles: Vec<TileRecord> = grid
+            .terrains
+            .iter()
+            .map(|(coords, terrain)| TileRecord {
+                coords: *coords,
+                terrain: *terrain,
+                elevation: grid.elevation(*coords),
+                properties: grid.properties.get(coords).cloned().unwrap_or_default(),
+            })
+            .collect();
+        tiles.sort_by_key(|tile| tile.coords);
+        let sorted = |edges: &HashSet<HexEdge>| {
+            let mut edges: Vec<HexEdge> = edges.iter().copied().collect();
+            edges.sort();
+            edges
+        };
+        Self {
+            version: MAP_FORMAT_VERSION,
+            settings: settings.clone(),
+            tiles,
+            spawn_points: grid.spawn_points.clone(),
+            rivers: sorted(&grid.rivers),
+            roads: sorted(&grid.roads),
+        }
+    }
+
+    /// The data of a grid holding the tiles of the map, without any spawned tile.
+    pub fn to_grid(&self) -> Grid {
+        let mut grid = Grid::default();
+        for tile in self.tiles.iter() {
+            grid.terrains.insert(tile.coords, tile.terrain);
+            grid.elevations.insert(tile.coords, tile.elevation);
+            if !tile.properties.is_empty() {
+                grid.properties.insert(tile.coords, tile.properties.clone());
+            }
+        }
+        grid.spawn_points = self.spawn_points.clone();
+        grid.rivers = self.rivers.iter().copied().collect();
+        grid.roads = self.roads.iter().copied().collect();
+        grid
+    }
+
+    pub fn to_ron(&self) -> Result<String, MapError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, MapError> {
+        // Check the version first so that older maps report it rather than a parse error.
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+        let header: Header = ron::de::from_str(text)?;
+        if header.version != MAP_FORMAT_VERSION {
+            return Err(MapError::UnsupportedVersion(header.version));
+        }
+        Ok(ron::de::from_str(text)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), MapError> {
+        fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MapError> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+}
+
+/// Replace the tiles of the `DefaultGrid` by the map stored at the path.
+/// The other grids are left alone.
+pub struct LoadMap(pub PathBuf);
+
+/// Write the `DefaultGrid` to the path.
+pub struct SaveMap(pub PathBuf);
+
+/// Load the map in place of the `DefaultGrid`, so that the units standing on
+/// it keep their `OnGrid`. They are placed again on the loaded tiles.
+pub fn load_map(
+    mut commands: Commands,
+    mut events: EventReader<LoadMap>,
+    mut default_settings: ResMut<GridSettings>,
+    grids: Query<(Entity, &Grid), With<DefaultGrid>>,
+    units: Query<(Entity, &OnGrid)>,
+) {
+    for LoadMap(path) in events.iter() {
+        let map = match MapFile::load(path) {
+            Ok(map) => map,
+            Err(err) => {
+                error!("Cannot load map {}: {}", path.display(), err);
+                continue;
+            }
+        };
+        *default_settings = map.settings.clone();
+        let (grid, old) = match grids.iter().next() {
+            Some(grid) => grid,
+            None => {
+                let grid = map.to_grid();
+                let grid = spawn_grid(&mut commands, map.settings, grid);
+                commands.entity(grid).insert(DefaultGrid);
+                continue;
+            }
+        };
+        for chunk in old.chunks.values() {
+            commands.entity(*chunk).despawn_recursive();
+        }
+        commands
+            .entity(grid)
+            .insert(map.to_grid())
+            .insert(map.settings)
+            .insert(Fog::default())
+            .insert(Occupancy::default())
+            .insert(TileHighlight::default())
+            .remove::<Generated>();
+        // Inserted again so that `update_occupancy` places them on the new tiles.
+        for (unit, on_grid) in units.iter() {
+            if on_grid.0 == grid {
+                commands.entity(unit).insert(*on_grid);
+            }
+        }
+    }
+}
+
+pub fn save_map(
+    mut events: EventReader<SaveMap>,
+    grids: Query<(&GridSettings, &Grid), With<DefaultGrid>>,
+) {
+    for SaveMap(path) in events.iter() {
+        let (settings, grid) = match grids.iter().next() {
+            Some(grid) => grid,
+            None => continue,
+        };
+        if let Err(err) = MapFile::from_grid(settings, grid).save(path) {
+            error!("Cannot save map {}: {}", path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{app::Events, ecs::system::CommandQueue};
+
+    use super::*;
+    use crate::tilemap::{
+        tests::{grid_tiles, headless_app},
+        Connectivity, GridKind, HeightmapSettings, OnTile, Tile,
+    };
+
+    fn saved_map(app: &mut App) -> MapFile {
+        let mut grids = app
+            .world
+            .query_filtered::<(&GridSettings, &Grid), With<DefaultGrid>>();
+        let (settings, grid) = grids.iter(&app.world).next().unwrap();
+        MapFile::from_grid(settings, grid)
+    }
+
+    #[test]
+    fn maps_round_trip_through_ron() {
+        let mut app = headless_app();
+        app.update();
+        let mut map = saved_map(&mut app);
+        assert_eq!(map.tiles.len(), 1 + 3 * 5 * 6);
+        map.tiles[3]
+            .properties
+            .insert("name".into(), "Old Ford".into());
+        map.spawn_points.push(SpawnPoint {
+            coords: HexCoord::new(2, -1),
+            faction: Faction(1),
+        });
+        map.roads.push(HexEdge::new(HexCoord::ZERO, 2));
+
+        let text = map.to_ron().unwrap();
+        assert_eq!(MapFile::from_ron(&text).unwrap(), map);
+
+        let future = text.replacen("version: 1", "version: 99", 1);
+        assert!(matches!(
+            MapFile::from_ron(&future),
+            Err(MapError::UnsupportedVersion(99))
+        ));
+    }
+
+    #[test]
+    fn loading_a_map_rebuilds_the_grid() {
+        let mut app = headless_app();
+        app.update();
+        let mut map = saved_map(&mut app);
+        map.settings.radius = 3;
+        map.tiles.retain(|tile| tile.coords.length() <= 3);
+        map.rivers
+            .retain(|edge| edge.tiles().iter().all(|coords| coords.length() <= 3));
+        for tile in map.tiles.iter_mut() {
+            tile.terrain = Terrain::Sand;
+            tile.elevation = 0.25;
+        }
+        map.spawn_points.push(SpawnPoint {
+            coords: HexCoord::ZERO,
+            faction: Faction(0),
+        });
+        let path = std::env::temp_dir().join(format!("map-{}.ron", std::process::id()));
+        map.save(&path).unwrap();
+
+        let old_tiles = grid_tiles(&mut app);
+        app.world
+            .get_resource_mut::<Events<LoadMap>>()
+            .unwrap()
+            .send(LoadMap(path.clone()));
+        app.update();
+
+        for tile in old_tiles.values() {
+            assert!(app.world.get_entity(*tile).is_none());
+        }
+        let tiles = grid_tiles(&mut app);
+        assert_eq!(tiles.len(), 1 + 3 * 3 * 4);
+        for (coords, tile) in tiles {
+            assert_eq!(app.world.get::<Tile>(tile).unwrap().coords, coords);
+        }
+        assert_eq!(saved_map(&mut app), map);
+        // The loaded map is now the one shaped by the settings resource.
+        assert_eq!(
+            *app.world.get_resource::<GridSettings>().unwrap(),
+            map.settings
+        );
+
+        app.world
+            .get_resource_mut::<Events<SaveMap>>()
+            .unwrap()
+            .send(SaveMap(path.clone()));
+        app.update();
+        assert_eq!(MapFile::load(&path).unwrap(), map);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn loading_a_map_replaces_the_default_grid_only() {
+        let mut app = headless_app();
+        app.update();
+        let mut grids = app.world.query_filtered::<Entity, With<DefaultGrid>>();
+        let main = grids.iter(&app.world).next().unwrap();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        let arena_settings = GridSettings {
+            radius: 2,
+            kind: GridKind::Square(Connectivity::Four),
+            ..Default::default()
+        };
+        let arena = spawn_grid(&mut commands, arena_settings, Grid::default());
+        queue.apply(&mut app.world);
+        let unit = app
+            .world
+            .spawn()
+            .insert(OnTile(HexCoord::ZERO))
+            .insert(OnGrid(main))
+            .id();
+        app.update();
+
+        let mut map = saved_map(&mut app);
+        for tile in map.tiles.iter_mut() {
+            tile.terrain = Terrain::Sand;
+        }
+        let path = std::env::temp_dir().join(format!("default-map-{}.ron", std::process::id()));
+        map.save(&path).unwrap();
+        app.world
+            .get_resource_mut::<Events<LoadMap>>()
+            .unwrap()
+            .send(LoadMap(path.clone()));
+        app.update();
+
+        // The units keep standing on the same grid, now holding the map.
+        assert!(app.world.get::<Generated>(main).is_none());
+        assert_eq!(app.world.get::<OnGrid>(unit), Some(&OnGrid(main)));
+        let occupancy = app.world.get::<Occupancy>(main).unwrap();
+        assert_eq!(
+            occupancy.position(unit),
+            Some(app.world.get::<OnTile>(unit).unwrap().0)
+        );
+        assert_eq!(saved_map(&mut app), map);
+        assert_eq!(app.world.get::<Grid>(arena).unwrap().tiles.len(), 5 * 5);
+
+        // Only the default grid is saved, whatever the order of the grids.
+        std::fs::remove_file(&path).unwrap();
+        app.world
+            .get_resource_mut::<Events<SaveMap>>()
+            .unwrap()
+            .send(SaveMap(path.clone()));
+        app.update();
+        assert_eq!(MapFile::load(&path).unwrap(), map);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn maps_loaded_at_startup_are_not_generated_again() {
+        let mut app = headless_app();
+        app.update();
+        let mut map = saved_map(&mut app);
+        for tile in map.tiles.iter_mut() {
+            tile.terrain = Terrain::Sand;
+        }
+        map.roads.push(HexEdge::new(HexCoord::ZERO, 2));
+        let path = std::env::temp_dir().join(format!("startup-map-{}.ron", std::process::id()));
+        map.save(&path).unwrap();
+
+        // Before the first update, when the generator settings count as changed.
+        let mut app = headless_app();
+        app.world
+            .get_resource_mut::<Events<LoadMap>>()
+            .unwrap()
+            .send(LoadMap(path.clone()));
+        app.update();
+        assert_eq!(saved_map(&mut app), map);
+
+        app.world
+            .get_resource_mut::<HeightmapSettings>()
+            .unwrap()
+            .max_elevation *= 2.;
+        app.update();
+        assert_eq!(saved_map(&mut app), map);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,548 @@
+use std::{collections::HashMap, fmt};
+
+use bevy::prelude::*;
+
+use super::{Faction, Grid, GridSettings, HexCoord, OnGrid, OnTile};
+
+/// Which units may share a tile.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackingRules {
+    /// Most units standing on one tile.
+    pub max_units: usize,
+    /// Whether units of different factions may stand on the same tile.
+    pub mixed_factions: bool,
+}
+
+impl Default for StackingRules {
+    fn default() -> Self {
+        Self {
+            max_units: 1,
+            mixed_factions: false,
+        }
+    }
+}
+
+/// Why a unit can't stand on a tile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OccupancyError {
+    /// The grid holds no tile there.
+    OffGrid(HexCoord),
+    /// The terrain of the tile can't be walked on.
+    Impassable(HexCoord),
+    /// The stacking rules don't allow another unit on the tile.
+    Occupied(HexCoord),
+    /// The unit isn't standing on the grid.
+    NotPlaced(Entity),
+}
+
+impl fmt::Display for OccupancyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OccupancyError::OffGrid(coords) => write!(f, "no tile at {:?}", coords),
+            OccupancyError::Impassable(coords) => write!(f, "tile {:?} is impassable", coords),
+            OccupancyError::Occupied(coords) => write!(f, "tile {:?} is occupied", coords),
+            OccupancyError::NotPlaced(unit) => write!(f, "unit {:?} is not on the grid", unit),
+        }
+    }
+}
+
+impl std::error::Error for OccupancyError {}
+
+/// Which units stand on which tile of a grid.
+#[derive(Component, Default, Debug)]
+pub struct Occupancy {
+    units: HashMap<HexCoord, Vec<Entity>>,
+    /// Tile and faction of every unit.
+    placed: HashMap<Entity, (HexCoord, Option<Faction>)>,
+}
+
+impl Occupancy {
+    /// Units standing on `coords`, in the order they arrived.
+    pub fn units(&self, coords: HexCoord) -> &[Entity] {
+        self.units
+            .get(&coords)
+            .map_or(&[], |units| units.as_slice())
+    }
+
+    pub fn is_occupied(&self, coords: HexCoord) -> bool {
+        !self.units(coords).is_empty()
+    }
+
+    /// Tile the unit stands on, if it is on the grid.
+    pub fn position(&self, unit: Entity) -> Option<HexCoord> {
+        self.placed.get(&unit).map(|(coords, _)| *coords)
+    }
+
+    /// Faction the unit was placed with.
+    pub fn faction(&self, unit: Entity) -> Option<Faction> {
+        self.placed.get(&unit).and_then(|(_, faction)| *faction)
+    }
+
+    /// Whether a unit of `faction` may stand on `coords`. `unit` itself
+    /// doesn't count against the stacking rules.
+    pub fn check(
+        &self,
+        grid: &Grid,
+        rules: &StackingRules,
+        unit: Entity,
+        faction: Option<Faction>,
+        coords: HexCoord,
+    ) -> Result<(), OccupancyError> {
+        let terrain = grid
+            .terrain(coords)
+            .ok_or(OccupancyError::OffGrid(coords))?;
+        if !terrain.properties().passable {
+            return Err(OccupancyError::Impassable(coords));
+        }
+        let others: Vec<Entity> = self
+            .units(coords)
+            .iter()
+            .copied()
+            .filter(|other| *other != unit)
+            .collect();
+        let foreign = others.iter().any(|other| self.placed[other].1 != faction);
+        if others.len() >= rules.max_units || (foreign && !rules.mixed_factions) {
+            return Err(OccupancyError::Occupied(coords));
+        }
+        Ok(())
+    }
+
+    /// Put the unit on `coords`, taking it off the tile it stood on.
+    pub fn place(
+        &mut self,
+        grid: &Grid,
+        rules: &StackingRules,
+        unit: Entity,
+        faction: Option<Faction>,
+        coords: HexCoord,
+    ) -> Result<(), OccupancyError> {
+        self.check(grid, rules, unit, faction, coords)?;
+        self.remove(unit);
+        self.units.entry(coords).or_default().push(unit);
+        self.placed.insert(unit, (coords, faction));
+        Ok(())
+    }
+
+    /// Move a unit of the grid to `to`. Returns the tile it left.
+    pub fn move_unit(
+        &mut self,
+        grid: &Grid,
+        rules: &StackingRules,
+        unit: Entity,
+        to: HexCoord,
+    ) -> Result<HexCoord, OccupancyError> {
+        let (from, faction) = *self
+            .placed
+            .get(&unit)
+            .ok_or(OccupancyError::NotPlaced(unit))?;
+        if from != to {
+            self.place(grid, rules, unit, faction, to)?;
+        }
+        Ok(from)
+    }
+
+    /// Take a unit off the grid. Returns the tile it stood on.
+    pub fn remove(&mut self, unit: Entity) -> Option<HexCoord> {
+        let (coords, _) = self.placed.remove(&unit)?;
+        let units = self.units.get_mut(&coords).unwrap();
+        units.retain(|other| *other != unit);
+        if units.is_empty() {
+            self.units.remove(&coords);
+        }
+        Some(coords)
+    }
+
+    /// Closest tile to `near` the unit, of `faction`, may be spawned on.
+    pub fn spawn_tile(
+        &self,
+        grid: &Grid,
+        settings: &GridSettings,
+        rules: &StackingRules,
+        unit: Entity,
+        faction: Option<Faction>,
+        near: HexCoord,
+    ) -> Option<HexCoord> {
+        settings
+            .coords()
+            .into_iter()
+            .filter(|coords| self.check(grid, rules, unit, faction, *coords).is_ok())
+            .min_by_key(|coords| settings.distance(near, *coords))
+    }
+}
+
+/// Sent when a unit steps onto a tile, after the `TileExited` of the tile it left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileEntered {
+    pub unit: Entity,
+    /// Entity of the tile, if its chunk is spawned without merged meshes.
+    pub tile: Option<Entity>,
+    /// Tile the unit left, `None` when it was just placed on the grid.
+    pub from: Option<HexCoord>,
+    pub to: HexCoord,
+}
+
+/// Sent when a unit leaves a tile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileExited {
+    pub unit: Entity,
+    /// Entity of the tile, if its chunk is spawned without merged meshes.
+    pub tile: Option<Entity>,
+    pub from: HexCoord,
+    /// Tile the unit went to, `None` when it was taken off the grid.
+    pub to: Option<HexCoord>,
+}
+
+/// Keep the occupancy of every grid in line with the units standing on it,
+/// as told by their `OnGrid`, and tell which tiles they entered and exited.
+///
+/// New units, units moved to another grid, and every unit when its grid is
+/// replaced are placed on the free tile closest to their position. Moves onto
+/// a tile the unit can't stand on are undone.
+#[allow(clippy::type_complexity)]
+pub fn update_occupancy(
+    rules: Res<StackingRules>,
+    mut grids: Query<(Entity, &Grid, &GridSettings, &mut Occupancy)>,
+    mut units: Query<(
+        Entity,
+        &mut OnTile,
+        Option<&Faction>,
+        &OnGrid,
+        ChangeTrackers<OnGrid>,
+    )>,
+    removed: RemovedComponents<OnTile>,
+    removed_from_grid: RemovedComponents<OnGrid>,
+    mut entered: EventWriter<TileEntered>,
+    mut exited: EventWriter<TileExited>,
+) {
+    for unit in removed.iter().chain(removed_from_grid.iter()) {
+        take_off(&mut grids, &mut exited, unit, None);
+    }
+    for (unit, mut on_tile, faction, on_grid, on_grid_tracker) in units.iter_mut() {
+        let changed_grid = on_grid_tracker.is_changed();
+        if changed_grid && !on_grid_tracker.is_added() {
+            take_off(&mut grids, &mut exited, unit, Some(on_grid.0));
+        }
+        let (_, grid, settings, mut occupancy) = match grids.get_mut(on_grid.0) {
+            Ok(grid) => grid,
+            Err(_) => continue,
+        };
+        let new_grid = occupancy.is_added();
+        if (!new_grid && !changed_grid && !on_tile.is_changed())
+            || occupancy.position(unit) == Some(on_tile.0)
+        {
+            continue;
+        }
+        let tile = |coords: HexCoord| grid.tiles.get(&coords).copied();
+        let from = occupancy.position(unit);
+        let result = match from {
+            Some(_) => occupancy
+                .move_unit(grid, &rules, unit, on_tile.0)
+                .map(|_| ()),
+            None => {
+                let faction = faction.copied();
+                match occupancy.spawn_tile(grid, settings, &rules, unit, faction, on_tile.0) {
+                    Some(coords) => {
+                        on_tile.0 = coords;
+                        occupancy.place(grid, &rules, unit, faction, coords)
+                    }
+                    None => Err(OccupancyError::Occupied(on_tile.0)),
+                }
+            }
+        };
+        match result {
+            Ok(()) => {
+                let to = on_tile.0;
+                if let Some(from) = from {
+                    exited.send(TileExited {
+                        unit,
+                        tile: tile(from),
+                        from,
+                        to: Some(to),
+                    });
+                }
+                entered.send(TileEntered {
+                    unit,
+                    tile: tile(to),
+                    from,
+                    to,
+                });
+            }
+            Err(err) => {
+                warn!("Unit {:?} can't stand on {:?}: {}", unit, on_tile.0, err);
+                if let Some(coords) = from {
+                    on_tile.0 = coords;
+                }
+            }
+        }
+    }
+}
+
+/// Take a unit off every grid but `keep`.
+fn take_off(
+    grids: &mut Query<(Entity, &Grid, &GridSettings, &mut Occupancy)>,
+    exited: &mut EventWriter<TileExited>,
+    unit: Entity,
+    keep: Option<Entity>,
+) {
+    for (grid_entity, grid, _, mut occupancy) in grids.iter_mut() {
+        if Some(grid_entity) == keep {
+            continue;
+        }
+        if let Some(from) = occupancy.remove(unit) {
+            exited.send(TileExited {
+                unit,
+                tile: grid.tiles.get(&from).copied(),
+                from,
+                to: None,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{app::Events, ecs::system::CommandQueue};
+
+    use crate::tilemap::{
+        spawn_grid,
+        tests::{flat_grid, headless_app},
+        DefaultGrid, Terrain,
+    };
+
+    #[test]
+    fn stacking_rules_limit_units_per_tile() {
+        let settings = GridSettings::default();
+        let mut grid = flat_grid(&settings);
+        grid.terrains.insert(HexCoord::new(1, 0), Terrain::Water);
+        let (a, b, c) = (
+            Entity::from_raw(1),
+            Entity::from_raw(2),
+            Entity::from_raw(3),
+        );
+        let rules = StackingRules {
+            max_units: 2,
+            mixed_factions: false,
+        };
+        let mut occupancy = Occupancy::default();
+        let red = Some(Faction(0));
+        let blue = Some(Faction(1));
+
+        occupancy
+            .place(&grid, &rules, a, red, HexCoord::ZERO)
+            .unwrap();
+        assert_eq!(
+            occupancy.place(&grid, &rules, b, blue, HexCoord::ZERO),
+            Err(OccupancyError::Occupied(HexCoord::ZERO))
+        );
+        occupancy
+            .place(&grid, &rules, b, red, HexCoord::ZERO)
+            .unwrap();
+        assert_eq!(
+            occupancy.place(&grid, &rules, c, red, HexCoord::ZERO),
+            Err(OccupancyError::Occupied(HexCoord::ZERO))
+        );
+        assert_eq!(occupancy.units(HexCoord::ZERO), [a, b]);
+
+        assert_eq!(
+            occupancy.move_unit(&grid, &rules, a, HexCoord::new(1, 0)),
+            Err(OccupancyError::Impassable(HexCoord::new(1, 0)))
+        );
+        assert_eq!(
+            occupancy.move_unit(&grid, &rules, a, HexCoord::new(9, 0)),
+            Err(OccupancyError::OffGrid(HexCoord::new(9, 0)))
+        );
+        assert_eq!(
+            occupancy.move_unit(&grid, &rules, c, HexCoord::ZERO),
+            Err(OccupancyError::NotPlaced(c))
+        );
+        assert_eq!(
+            occupancy.move_unit(&grid, &rules, a, HexCoord::new(0, 1)),
+            Ok(HexCoord::ZERO)
+        );
+        assert_eq!(occupancy.units(HexCoord::ZERO), [b]);
+        assert_eq!(occupancy.position(a), Some(HexCoord::new(0, 1)));
+
+        assert_eq!(occupancy.remove(b), Some(HexCoord::ZERO));
+        assert!(!occupancy.is_occupied(HexCoord::ZERO));
+    }
+
+    #[test]
+    fn units_spawn_on_the_closest_free_tile() {
+        let settings = GridSettings::default();
+        let mut grid = flat_grid(&settings);
+        grid.terrains.insert(HexCoord::new(1, 0), Terrain::Water);
+        let rules = StackingRules::default();
+        let mut occupancy = Occupancy::default();
+        occupancy
+            .place(&grid, &rules, Entity::from_raw(1), None, HexCoord::ZERO)
+            .unwrap();
+        let near = HexCoord::new(1, 0);
+        let spawn = occupancy
+            .spawn_tile(&grid, &settings, &rules, Entity::from_raw(2), None, near)
+            .unwrap();
+        assert_eq!(settings.distance(near, spawn), 1);
+        assert_ne!(spawn, HexCoord::ZERO);
+    }
+
+    #[test]
+    fn blocked_moves_are_undone() {
+        let mut app = headless_app();
+        app.update();
+        let mut grids = app.world.query::<(Entity, &mut Grid)>();
+        let (grid_entity, mut grid) = grids.iter_mut(&mut app.world).next().unwrap();
+        for terrain in grid.terrains.values_mut() {
+            *terrain = Terrain::Grass;
+        }
+        grid.terrains.insert(HexCoord::new(2, 0), Terrain::Water);
+        let spawn_unit = |app: &mut App| {
+            app.world
+                .spawn()
+                .insert(OnTile(HexCoord::ZERO))
+                .insert(Faction(0))
+                .insert(OnGrid(grid_entity))
+                .id()
+        };
+        let a = spawn_unit(&mut app);
+        let b = spawn_unit(&mut app);
+        app.update();
+        let pos = |app: &App, unit| app.world.get::<OnTile>(unit).unwrap().0;
+        // Both asked for the center, the second one is pushed aside.
+        assert_eq!(pos(&app, a), HexCoord::ZERO);
+        assert_eq!(HexCoord::ZERO.distance(pos(&app, b)), 1);
+
+        app.world.get_mut::<OnTile>(a).unwrap().0 = HexCoord::new(1, 0);
+        app.update();
+        assert_eq!(pos(&app, a), HexCoord::new(1, 0));
+        for blocked in [HexCoord::new(2, 0), pos(&app, b)] {
+            app.world.get_mut::<OnTile>(a).unwrap().0 = blocked;
+            app.update();
+            assert_eq!(pos(&app, a), HexCoord::new(1, 0));
+        }
+
+        app.world.despawn(a);
+        app.update();
+        let mut grids = app.world.query::<&Occupancy>();
+        let occupancy = grids.iter(&app.world).next().unwrap();
+        assert!(!occupancy.is_occupied(HexCoord::new(1, 0)));
+    }
+
+    #[test]
+    fn moves_send_exited_then_entered() {
+        let mut app = headless_app();
+        app.update();
+        let mut grids = app.world.query::<(Entity, &mut Grid)>();
+        let (grid_entity, mut grid) = grids.iter_mut(&mut app.world).next().unwrap();
+        for terrain in grid.terrains.values_mut() {
+            *terrain = Terrain::Grass;
+        }
+        let tiles = grid.tiles.clone();
+        let unit = app
+            .world
+            .spawn()
+            .insert(OnTile(HexCoord::ZERO))
+            .insert(OnGrid(grid_entity))
+            .id();
+        let mut entered_reader = app
+            .world
+            .get_resource::<Events<TileEntered>>()
+            .unwrap()
+            .get_reader();
+        let mut exited_reader = app
+            .world
+            .get_resource::<Events<TileExited>>()
+            .unwrap()
+            .get_reader();
+        let mut drain = |app: &App| {
+            let entered: Vec<TileEntered> = entered_reader
+                .iter(app.world.get_resource::<Events<TileEntered>>().unwrap())
+                .copied()
+                .collect();
+            let exited: Vec<TileExited> = exited_reader
+                .iter(app.world.get_resource::<Events<TileExited>>().unwrap())
+                .copied()
+                .collect();
+            (entered, exited)
+        };
+        app.update();
+        let to = HexCoord::ZERO;
+        let spawned = TileEntered {
+            unit,
+            tile: tiles.get(&to).copied(),
+            from: None,
+            to,
+        };
+        assert_eq!(drain(&app), (vec![spawned], vec![]));
+
+        let next = HexCoord::new(0, 1);
+        app.world.get_mut::<OnTile>(unit).unwrap().0 = next;
+        app.update();
+        let (entered, exited) = drain(&app);
+        assert_eq!(
+            exited,
+            [TileExited {
+                unit,
+                tile: tiles.get(&to).copied(),
+                from: to,
+                to: Some(next),
+            }]
+        );
+        assert_eq!(entered.len(), 1);
+        assert_eq!(entered[0].from, Some(to));
+        assert_eq!(entered[0].tile, tiles.get(&next).copied());
+        assert!(entered[0].tile.is_some());
+
+        // A move that is undone is neither entered nor exited.
+        app.world.get_mut::<OnTile>(unit).unwrap().0 = HexCoord::new(9, 0);
+        app.update();
+        assert_eq!(drain(&app), (vec![], vec![]));
+
+        app.world.despawn(unit);
+        app.update();
+        let (entered, exited) = drain(&app);
+        assert!(entered.is_empty());
+        assert_eq!(exited[0].to, None);
+        assert_eq!(exited[0].from, next);
+    }
+
+    #[test]
+    fn units_stand_on_their_own_grid() {
+        let mut app = headless_app();
+        app.update();
+        let settings = GridSettings::default();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        let arena = spawn_grid(&mut commands, settings.clone(), flat_grid(&settings));
+        queue.apply(&mut app.world);
+        let mut grids = app
+            .world
+            .query_filtered::<(Entity, &mut Grid), With<DefaultGrid>>();
+        let (main, mut grid) = grids.iter_mut(&mut app.world).next().unwrap();
+        *grid = flat_grid(&settings);
+
+        let spawn_unit = |app: &mut App, grid| {
+            app.world
+                .spawn()
+                .insert(OnTile(HexCoord::ZERO))
+                .insert(OnGrid(grid))
+                .id()
+        };
+        let a = spawn_unit(&mut app, main);
+        let b = spawn_unit(&mut app, arena);
+        app.update();
+        fn occupancy(app: &App, grid: Entity) -> &Occupancy {
+            app.world.get::<Occupancy>(grid).unwrap()
+        }
+        // Both stand on the center of their own grid.
+        assert_eq!(occupancy(&app, main).units(HexCoord::ZERO), [a]);
+        assert_eq!(occupancy(&app, arena).units(HexCoord::ZERO), [b]);
+
+        // Moving to the other grid takes the unit off the first one.
+        app.world.get_mut::<OnGrid>(b).unwrap().0 = main;
+        app.update();
+        assert!(!occupancy(&app, arena).is_occupied(HexCoord::ZERO));
+        let pos = app.world.get::<OnTile>(b).unwrap().0;
+        assert_eq!(HexCoord::ZERO.distance(pos), 1);
+        assert_eq!(occupancy(&app, main).position(b), Some(pos));
+    }
+}
@@ -0,0 +1,557 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use bevy::prelude::Entity;
+
+use super::{Grid, GridSettings, HexCoord, Occupancy, StackingRules, Terrain};
+
+/// Movement rules on top of the terrain costs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathCosts {
+    /// Extra movement points per unit of elevation climbed. Going down is free.
+    pub climb_cost: f32,
+    /// Steepest climb a unit can make in one step.
+    pub max_climb: f32,
+    /// Extra movement points to cross a river without a road.
+    pub river_crossing_cost: u32,
+    /// Movement points of a step along a road, whatever the terrain and climb.
+    pub road_cost: u32,
+}
+
+impl Default for PathCosts {
+    fn default() -> Self {
+        Self {
+            climb_cost: 2.,
+            max_climb: 0.5,
+            river_crossing_cost: 2,
+            road_cost: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Path {
+    /// Every tile of the path, from the start to the goal included.
+    pub tiles: Vec<HexCoord>,
+    pub cost: u32,
+}
+
+impl PathCosts {
+    /// Movement points spent to step from `from` onto the adjacent tile `to`,
+    /// or `None` if that step is not allowed.
+    pub fn step_cost(
+        &self,
+        grid: &Grid,
+        settings: &GridSettings,
+        from: HexCoord,
+        to: HexCoord,
+    ) -> Option<u32> {
+        let properties = grid.terrain(to)?.properties();
+        if !properties.passable {
+            return None;
+        }
+        let climb = (grid.elevation(to) - grid.elevation(from)).max(0.);
+        if climb > self.max_climb {
+            return None;
+        }
+        if grid.has_road(settings, from, to) {
+            return Some(self.road_cost);
+        }
+        let river = if grid.has_river(settings, from, to) {
+            self.river_crossing_cost
+        } else {
+            0
+        };
+        Some(properties.movement_cost + (climb * self.climb_cost).ceil() as u32 + river)
+    }
+
+    /// Cheapest step onto any passable terrain or along a road, which keeps
+    /// the A* heuristic admissible.
+    fn min_step_cost(&self) -> u32 {
+        Terrain::ALL
+            .iter()
+            .map(|terrain| terrain.properties())
+            .filter(|properties| properties.passable)
+            .map(|properties| properties.movement_cost)
+            .min()
+            .unwrap_or(1)
+            .min(self.road_cost)
+    }
+}
+
+/// Cheapest legal route from `from` to `to` over the tiles of `grid`. On
+/// wrapping maps, both tiles are wrapped around the seams first.
+pub fn find_path(
+    grid: &Grid,
+    settings: &GridSettings,
+    costs: &PathCosts,
+    from: HexCoord,
+    to: HexCoord,
+) -> Option<Path> {
+    search_path(grid, settings, costs, from, to, |_| true)
+}
+
+/// Cheapest route for `unit` from its tile to `to`, around the tiles the
+/// occupancy doesn't let it stand on.
+pub fn find_unit_path(
+    grid: &Grid,
+    settings: &GridSettings,
+    costs: &PathCosts,
+    occupancy: &Occupancy,
+    rules: &StackingRules,
+    unit: Entity,
+    to: HexCoord,
+) -> Option<Path> {
+    let from = occupancy.position(unit)?;
+    let faction = occupancy.faction(unit);
+    search_path(grid, settings, costs, from, to, |coords| {
+        occupancy.check(grid, rules, unit, faction, coords).is_ok()
+    })
+}
+
+/// A* search of `find_path`, only stepping onto the tiles `can_enter` accepts.
+fn search_path(
+    grid: &Grid,
+    settings: &GridSettings,
+    costs: &PathCosts,
+    from: HexCoord,
+    to: HexCoord,
+    can_enter: impl Fn(HexCoord) -> bool,
+) -> Option<Path> {
+    let (from, to) = (settings.wrap(from), settings.wrap(to));
+    grid.terrain(from)?;
+    let min_cost = costs.min_step_cost();
+    let heuristic = |coords: HexCoord| settings.distance(coords, to) as u32 * min_cost;
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<HexCoord, HexCoord> = HashMap::new();
+    let mut best: HashMap<HexCoord, u32> = HashMap::new();
+    best.insert(from, 0);
+    open.push(Reverse((heuristic(from), 0, from)));
+
+    while let Some(Reverse((_, cost, current))) = open.pop() {
+        if current == to {
+            let mut tiles = vec![current];
+            while let Some(previous) = came_from.get(tiles.last().unwrap()) {
+                tiles.push(*previous);
+            }
+            tiles.reverse();
+            return Some(Path { tiles, cost });
+        }
+        if cost > best[&current] {
+            // A cheaper way to `current` was found after this one was queued.
+            continue;
+        }
+        for next in settings.neighbors(current) {
+            if !can_enter(next) {
+                continue;
+            }
+            let step = match costs.step_cost(grid, settings, current, next) {
+                Some(step) => step,
+                None => continue,
+            };
+            let next_cost = cost + step;
+            if best.get(&next).map_or(true, |known| next_cost < *known) {
+                best.insert(next, next_cost);
+                came_from.insert(next, current);
+                open.push(Reverse((next_cost + heuristic(next), next_cost, next)));
+            }
+        }
+    }
+    None
+}
+
+/// Tiles a unit can reach with a movement budget, see [`movement_range`].
+#[derive(Clone, Debug, Default)]
+pub struct Reachable {
+    pub start: HexCoord,
+    costs: HashMap<HexCoord, u32>,
+    came_from: HashMap<HexCoord, HexCoord>,
+}
+
+impl Reachable {
+    /// Cheapest cost to reach `coords`, if it is in range.
+    pub fn cost(&self, coords: HexCoord) -> Option<u32> {
+        self.costs.get(&coords).copied()
+    }
+
+    pub fn contains(&self, coords: HexCoord) -> bool {
+        self.costs.contains_key(&coords)
+    }
+
+    /// Every reachable tile along with its cost, the start included.
+    pub fn iter(&self) -> impl Iterator<Item = (HexCoord, u32)> + '_ {
+        self.costs.iter().map(|(coords, cost)| (*coords, *cost))
+    }
+
+    /// Cheapest route from the start to `coords`, if it is in range.
+    pub fn path_to(&self, coords: HexCoord) -> Option<Path> {
+        let cost = self.cost(coords)?;
+        let mut tiles = vec![coords];
+        while let Some(previous) = self.came_from.get(tiles.last().unwrap()) {
+            tiles.push(*previous);
+        }
+        tiles.reverse();
+        Some(Path { tiles, cost })
+    }
+}
+
+/// Every tile reachable from `start` while spending at most `budget` movement
+/// points. On wrapping maps, `start` is wrapped around the seams first.
+pub fn movement_range(
+    grid: &Grid,
+    settings: &GridSettings,
+    costs: &PathCosts,
+    start: HexCoord,
+    budget: u32,
+) -> Reachable {
+    search_range(grid, settings, costs, start, budget, |_| true)
+}
+
+/// Every tile `unit` can reach from its tile while spending at most `budget`
+/// movement points, around the tiles the occupancy doesn't let it stand on.
+pub fn unit_movement_range(
+    grid: &Grid,
+    settings: &GridSettings,
+    costs: &PathCosts,
+    occupancy: &Occupancy,
+    rules: &StackingRules,
+    unit: Entity,
+    budget: u32,
+) -> Reachable {
+    let start = match occupancy.position(unit) {
+        Some(start) => start,
+        None => return Reachable::default(),
+    };
+    let faction = occupancy.faction(unit);
+    search_range(grid, settings, costs, start, budget, |coords| {
+        occupancy.check(grid, rules, unit, faction, coords).is_ok()
+    })
+}
+
+/// Dijkstra search of `movement_range`, only stepping onto the tiles `can_enter` accepts.
+fn search_range(
+    grid: &Grid,
+    settings: &GridSettings,
+    costs: &PathCosts,
+    start: HexCoord,
+    budget: u32,
+    can_enter: impl Fn(HexCoord) -> bool,
+) -> Reachable {
+    let start = settings.wrap(start);
+    let mut reachable = Reachable {
+        start,
+        ..Default::default()
+    };
+    if grid.terrain(start).is_none() {
+        return reachable;
+    }
+    let mut open = BinaryHeap::new();
+    reachable.costs.insert(start, 0);
+    open.push(Reverse((0, start)));
+
+    while let Some(Reverse((cost, current))) = open.pop() {
+        if cost > reachable.costs[&current] {
+            continue;
+        }
+        for next in settings.neighbors(current) {
+            if !can_enter(next) {
+                continue;
+            }
+            let step = match costs.step_cost(grid, settings, current, next) {
+                Some(step) => step,
+                None => continue,
+            };
+            let next_cost = cost + step;
+            if next_cost > budget {
+                continue;
+            }
+            if reachable
+                .costs
+                .get(&next)
+                .map_or(true, |known| next_cost < *known)
+            {
+                reachable.costs.insert(next, next_cost);
+                reachable.came_from.insert(next, current);
+                open.push(Reverse((next_cost, next)));
+            }
+        }
+    }
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::{
+        tests::{flat_grid, headless_app},
+        Connectivity, GridKind, HexEdge, WrapMode,
+    };
+
+    fn assert_connected(settings: &GridSettings, path: &Path) {
+        for step in path.tiles.windows(2) {
+            assert_eq!(settings.distance(step[0], step[1]), 1);
+        }
+    }
+
+    #[test]
+    fn straight_line_on_open_ground() {
+        let settings = GridSettings::default();
+        let grid = flat_grid(&settings);
+        let to = HexCoord::new(3, -1);
+        let path = find_path(&grid, &settings, &PathCosts::default(), HexCoord::ZERO, to).unwrap();
+        assert_eq!(path.cost, 3);
+        assert_eq!(path.tiles.first(), Some(&HexCoord::ZERO));
+        assert_eq!(path.tiles.last(), Some(&to));
+        assert_connected(&settings, &path);
+    }
+
+    #[test]
+    fn walks_around_water_and_forests() {
+        let settings = GridSettings::default();
+        let mut grid = flat_grid(&settings);
+        // A wall of water across the whole grid, except one forest gap at the edge.
+        for r in -5..=5 {
+            grid.terrains.insert(HexCoord::new(0, r), Terrain::Water);
+        }
+        grid.terrains.insert(HexCoord::new(0, 5), Terrain::Forest);
+        let from = HexCoord::new(-2, 0);
+        let to = HexCoord::new(2, 0);
+        let path = find_path(&grid, &settings, &PathCosts::default(), from, to).unwrap();
+        assert!(path.tiles.contains(&HexCoord::new(0, 5)));
+        assert!(path
+            .tiles
+            .iter()
+            .all(|t| grid.terrain(*t).unwrap().properties().passable));
+        assert_connected(&settings, &path);
+
+        grid.terrains.insert(HexCoord::new(0, 5), Terrain::Water);
+        assert!(find_path(&grid, &settings, &PathCosts::default(), from, to).is_none());
+    }
+
+    #[test]
+    fn climbing_costs_and_cliffs_block() {
+        let settings = GridSettings::default();
+        let mut grid = flat_grid(&settings);
+        let costs = PathCosts::default();
+        let hill = HexCoord::new(1, 0);
+        grid.elevations.insert(hill, 0.5);
+        assert_eq!(
+            costs.step_cost(&grid, &settings, HexCoord::ZERO, hill),
+            Some(2)
+        );
+        assert_eq!(
+            costs.step_cost(&grid, &settings, hill, HexCoord::ZERO),
+            Some(1)
+        );
+        grid.elevations.insert(hill, 0.6);
+        assert_eq!(
+            costs.step_cost(&grid, &settings, HexCoord::ZERO, hill),
+            None
+        );
+        let path = find_path(
+            &grid,
+            &settings,
+            &costs,
+            HexCoord::ZERO,
+            HexCoord::new(2, 0),
+        )
+        .unwrap();
+        assert!(!path.tiles.contains(&hill));
+    }
+
+    #[test]
+    fn roads_and_rivers_change_step_costs() {
+        let settings = GridSettings::default();
+        let mut grid = flat_grid(&settings);
+        let costs = PathCosts::default();
+        let (a, b, c) = (HexCoord::ZERO, HexCoord::new(1, 0), HexCoord::new(2, 0));
+        grid.terrains.insert(b, Terrain::Forest);
+        grid.rivers.insert(HexEdge::between(b, c).unwrap());
+        assert_eq!(costs.step_cost(&grid, &settings, a, b), Some(2));
+        assert_eq!(costs.step_cost(&grid, &settings, b, c), Some(3));
+
+        assert!(grid.add_road_along(&settings, &[a, b, c]));
+        assert_eq!(costs.step_cost(&grid, &settings, a, b), Some(1));
+        // The road bridges the river.
+        assert_eq!(costs.step_cost(&grid, &settings, b, c), Some(1));
+        assert!(!grid.add_road(&settings, a, c));
+
+        // Square and iso tiles have no edges to put roads on.
+        for kind in [
+            GridKind::Square(Connectivity::Eight),
+            GridKind::Iso(Connectivity::Four),
+        ] {
+            let settings = GridSettings {
+                kind,
+                ..Default::default()
+            };
+            let mut grid = flat_grid(&settings);
+            assert!(!grid.add_road(&settings, a, b));
+            assert!(!grid.add_road(&settings, a, HexCoord::new(1, 1)));
+            assert!(grid.roads.is_empty());
+        }
+    }
+
+    #[test]
+    fn paths_follow_roads() {
+        let settings = GridSettings::default();
+        let mut grid = flat_grid(&settings);
+        for coords in settings.coords() {
+            grid.terrains.insert(coords, Terrain::Forest);
+        }
+        // A detour along a road is cheaper than the straight way through the forest.
+        let road = [
+            HexCoord::new(-2, 0),
+            HexCoord::new(-2, -1),
+            HexCoord::new(-1, -2),
+            HexCoord::new(0, -2),
+            HexCoord::new(1, -2),
+            HexCoord::new(2, -2),
+            HexCoord::new(2, -1),
+            HexCoord::new(2, 0),
+        ];
+        assert!(grid.add_road_along(&settings, &road));
+        let path = find_path(
+            &grid,
+            &settings,
+            &PathCosts::default(),
+            road[0],
+            road[road.len() - 1],
+        )
+        .unwrap();
+        assert_eq!(path.tiles, road);
+        assert_eq!(path.cost, 7);
+    }
+
+    #[test]
+    fn range_on_open_ground_is_a_disk() {
+        let settings = GridSettings::default();
+        let grid = flat_grid(&settings);
+        let range = movement_range(&grid, &settings, &PathCosts::default(), HexCoord::ZERO, 3);
+        assert_eq!(range.iter().count(), 1 + 3 * 3 * 4);
+        for (coords, cost) in range.iter() {
+            assert_eq!(cost, coords.length() as u32);
+        }
+    }
+
+    #[test]
+    fn range_agrees_with_find_path() {
+        let settings = GridSettings::default();
+        let mut grid = flat_grid(&settings);
+        grid.terrains.insert(HexCoord::new(1, 0), Terrain::Water);
+        grid.terrains.insert(HexCoord::new(0, 1), Terrain::Forest);
+        grid.terrains
+            .insert(HexCoord::new(1, -1), Terrain::Mountain);
+        let costs = PathCosts::default();
+        let range = movement_range(&grid, &settings, &costs, HexCoord::ZERO, 4);
+        assert!(!range.contains(HexCoord::new(1, 0)));
+        for (coords, cost) in range.iter() {
+            assert!(cost <= 4);
+            let preview = range.path_to(coords).unwrap();
+            let path = find_path(&grid, &settings, &costs, HexCoord::ZERO, coords).unwrap();
+            assert_eq!(preview.cost, path.cost);
+            assert_eq!(preview.tiles.first(), Some(&HexCoord::ZERO));
+            assert_connected(&settings, &preview);
+        }
+    }
+
+    #[test]
+    fn units_path_around_other_units() {
+        let settings = GridSettings::default();
+        let grid = flat_grid(&settings);
+        let rules = StackingRules::default();
+        let costs = PathCosts::default();
+        let mut occupancy = Occupancy::default();
+        let (unit, blocker) = (Entity::from_raw(1), Entity::from_raw(2));
+        occupancy
+            .place(&grid, &rules, unit, None, HexCoord::ZERO)
+            .unwrap();
+        occupancy
+            .place(&grid, &rules, blocker, None, HexCoord::new(1, 0))
+            .unwrap();
+
+        let to = HexCoord::new(2, 0);
+        let path = find_unit_path(&grid, &settings, &costs, &occupancy, &rules, unit, to).unwrap();
+        assert_eq!(path.cost, 3);
+        assert!(!path.tiles.contains(&HexCoord::new(1, 0)));
+        assert_connected(&settings, &path);
+        assert!(find_unit_path(
+            &grid,
+            &settings,
+            &costs,
+            &occupancy,
+            &rules,
+            unit,
+            HexCoord::new(1, 0)
+        )
+        .is_none());
+
+        let range = unit_movement_range(&grid, &settings, &costs, &occupancy, &rules, unit, 2);
+        assert!(!range.contains(HexCoord::new(1, 0)));
+        assert_eq!(range.cost(to), None);
+        assert_eq!(range.iter().count(), 1 + 3 * 2 * 3 - 1 - 1);
+    }
+
+    #[test]
+    fn paths_cross_the_seam_of_wrapping_maps() {
+        let settings = GridSettings {
+            radius: 4,
+            wrap: WrapMode::Cylinder,
+            ..Default::default()
+        };
+        let mut grid = flat_grid(&settings);
+        let costs = PathCosts::default();
+        let from = HexCoord::new(-4, 1);
+        let to = HexCoord::new(5, -3);
+        let path = find_path(&grid, &settings, &costs, from, to).unwrap();
+        assert_eq!(path.cost, settings.distance(from, to) as u32);
+        assert_eq!(path.cost, 1);
+        assert_connected(&settings, &path);
+
+        // Tiles outside of the map are their copies on it.
+        let period = settings.wrap_periods()[0];
+        let path = find_path(&grid, &settings, &costs, from + period, to - period).unwrap();
+        assert_eq!(path.tiles, vec![from, to]);
+        let reachable = movement_range(&grid, &settings, &costs, from + period, 1);
+        assert_eq!(reachable.start, from);
+        assert_eq!(reachable.cost(to), Some(1));
+
+        // Rivers and roads across the seam are found from both sides.
+        grid.rivers.insert(settings.edge_between(from, to).unwrap());
+        assert_eq!(costs.step_cost(&grid, &settings, to, from), Some(3));
+        assert!(grid.add_road(&settings, to, from));
+        assert_eq!(grid.roads.len(), 1);
+        assert_eq!(costs.step_cost(&grid, &settings, from, to), Some(1));
+    }
+
+    #[test]
+    fn paths_on_a_headless_app() {
+        let mut app = headless_app();
+        app.update();
+        let settings = app.world.get_resource::<GridSettings>().unwrap().clone();
+        let costs = app.world.get_resource::<PathCosts>().unwrap().clone();
+        let mut grids = app.world.query::<&mut Grid>();
+        let mut grid = grids.iter_mut(&mut app.world).next().unwrap();
+        for coords in settings.coords() {
+            grid.terrains.insert(coords, Terrain::Grass);
+            grid.elevations.insert(coords, 0.);
+        }
+        let to = HexCoord::new(-4, 2);
+        let path = find_path(&grid, &settings, &costs, HexCoord::ZERO, to).unwrap();
+        assert_eq!(path.cost, 4);
+        assert_eq!(path.tiles.len(), 5);
+        assert_connected(&settings, &path);
+        // Outside of the grid.
+        assert!(find_path(
+            &grid,
+            &settings,
+            &costs,
+            HexCoord::ZERO,
+            HexCoord::new(9, 0)
+        )
+        .is_none());
+    }
+}
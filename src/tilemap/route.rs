@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_ext::shape::Line;
+
+use super::{
+    Chunk, ChunkCoord, ChunkSettings, Grid, GridKind, GridSettings, HexCoord, HexEdge, HexVertex,
+    TILE_HEIGHT,
+};
+
+/// Height of rivers and roads above the tiles, so that they don't flicker.
+const ROUTE_OFFSET: f32 = 0.01;
+
+/// Marks the river and road meshes of a grid, which are children of its chunks.
+#[derive(Component)]
+pub struct RouteMesh;
+
+pub struct RouteMaterials {
+    pub river: Handle<StandardMaterial>,
+    pub road: Handle<StandardMaterial>,
+}
+
+impl FromWorld for RouteMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world
+            .get_resource_mut::<Assets<StandardMaterial>>()
+            .expect("TileMapPlugin needs the StandardMaterial assets");
+        Self {
+            river: materials.add(StandardMaterial {
+                base_color: Color::rgb(0.25, 0.5, 0.9),
+                unlit: true,
+                ..Default::default()
+            }),
+            road: materials.add(StandardMaterial {
+                base_color: Color::rgb(0.45, 0.33, 0.2),
+                unlit: true,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// Top of a tile, which may lie across a seam of a wrapping map.
+fn tile_top(settings: &GridSettings, grid: &Grid, coords: HexCoord) -> Vec3 {
+    settings.get_global_pos(coords)
+        + Vec3::Y * (TILE_HEIGHT + grid.elevation(settings.wrap(coords)) + ROUTE_OFFSET)
+}
+
+/// A vertex sits at the mean position of the tops of its tiles.
+fn vertex_top(settings: &GridSettings, grid: &Grid, vertex: HexVertex) -> Vec3 {
+    vertex.tiles().iter().fold(Vec3::ZERO, |sum, coords| {
+        sum + tile_top(settings, grid, *coords)
+    }) / 3.
+}
+
+/// Segment of a river along `edge`, between the tiles of the edge.
+pub fn river_line(settings: &GridSettings, grid: &Grid, edge: HexEdge) -> Line {
+    let [start, end] = edge
+        .vertices()
+        .map(|vertex| vertex_top(settings, grid, vertex));
+    Line::new(start, end - start)
+}
+
+/// Segment of a road across `edge`, between the centers of its tiles.
+pub fn road_line(settings: &GridSettings, grid: &Grid, edge: HexEdge) -> Line {
+    let [start, end] = edge.tiles().map(|coords| tile_top(settings, grid, coords));
+    Line::new(start, end - start)
+}
+
+/// Rebuild the river and road meshes of the grids that changed.
+///
+/// Every spawned chunk gets the segments of the edges stored from its tiles,
+/// so that they move along with the chunk on wrapping maps.
+#[allow(clippy::type_complexity)]
+pub fn draw_routes(
+    mut commands: Commands,
+    materials: Res<RouteMaterials>,
+    chunk_settings: Res<ChunkSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    grids: Query<(&Grid, &GridSettings), Or<(Changed<Grid>, Changed<GridSettings>)>>,
+    chunks: Query<&Children, With<Chunk>>,
+    route_meshes: Query<(), With<RouteMesh>>,
+) {
+    for (grid, settings) in grids.iter() {
+        for chunk in grid.chunks.values() {
+            for child in chunks
+                .get(*chunk)
+                .iter()
+                .flat_map(|children| children.iter())
+            {
+                if route_meshes.get(*child).is_ok() {
+                    commands.entity(*child).despawn_recursive();
+                }
+            }
+        }
+        // Edges only exist between hex tiles.
+        if !matches!(settings.kind, GridKind::Hex(_)) {
+            continue;
+        }
+        let mut routes: HashMap<ChunkCoord, [Vec<Line>; 2]> = HashMap::new();
+        let mut add = |edge: &HexEdge, index: usize, line: Line| {
+            let chunk = ChunkCoord::of(edge.coords, chunk_settings.chunk_size);
+            routes.entry(chunk).or_default()[index].push(line);
+        };
+        for edge in grid.rivers.iter() {
+            add(edge, 0, river_line(settings, grid, *edge));
+        }
+        for edge in grid.roads.iter() {
+            add(edge, 1, road_line(settings, grid, *edge));
+        }
+        for (chunk, [rivers, roads]) in routes {
+            let chunk_entity = match grid.chunks.get(&chunk) {
+                Some(chunk_entity) => *chunk_entity,
+                None => continue,
+            };
+            let routes = [
+                (rivers, 0.3, materials.river.clone()),
+                (roads, 0.2, materials.road.clone()),
+            ];
+            for (lines, width, material) in routes {
+                if lines.is_empty() {
+                    continue;
+                }
+                let route = commands
+                    .spawn_bundle(PbrBundle {
+                        mesh: meshes.add(Line::strip_mesh(&lines, width * settings.tile_size)),
+                        material,
+                        ..Default::default()
+                    })
+                    .insert(RouteMesh)
+                    .id();
+                commands.entity(chunk_entity).push_children(&[route]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::{tests::headless_app, WrapMode};
+
+    #[test]
+    fn roads_and_rivers_get_meshes() {
+        let mut app = headless_app();
+        app.update();
+        let mut grids = app.world.query::<(&mut Grid, &GridSettings)>();
+        let (mut grid, settings) = grids.iter_mut(&mut app.world).next().unwrap();
+        let settings = settings.clone();
+        grid.rivers.clear();
+        grid.rivers.insert(HexEdge::new(HexCoord::ZERO, 1));
+        assert!(grid.add_road_along(&settings, &HexCoord::ZERO.line_to(HexCoord::new(3, -2))));
+        app.update();
+
+        let mut route_meshes = app
+            .world
+            .query_filtered::<(&Parent, &Handle<Mesh>, &Handle<StandardMaterial>), With<RouteMesh>>(
+            );
+        let materials = app.world.get_resource::<RouteMaterials>().unwrap();
+        let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+        let (mut river_vertices, mut road_vertices) = (0, 0);
+        for (parent, mesh, material) in route_meshes.iter(&app.world) {
+            assert!(app.world.get::<Chunk>(parent.0).is_some());
+            let count = meshes.get(mesh).unwrap().count_vertices();
+            if *material == materials.river {
+                river_vertices += count;
+            } else {
+                road_vertices += count;
+            }
+        }
+        // Six vertices per segment: one river edge and three road steps.
+        assert_eq!((river_vertices, road_vertices), (6, 18));
+
+        // Removing the roads rebuilds the meshes without them.
+        let (mut grid, _) = grids.iter_mut(&mut app.world).next().unwrap();
+        grid.roads.clear();
+        app.update();
+        assert_eq!(route_meshes.iter(&app.world).count(), 1);
+    }
+
+    #[test]
+    fn rivers_run_between_their_tiles() {
+        let settings = GridSettings::default();
+        let grid = Grid::default();
+        let edge = HexEdge::new(HexCoord::new(1, -1), 4);
+        let line = river_line(&settings, &grid, edge);
+        let middle = line.point_at(0.5);
+        let [a, b] = edge.tiles().map(|coords| settings.get_global_pos(coords));
+        let expected = (a + b) / 2. + Vec3::Y * (TILE_HEIGHT + ROUTE_OFFSET);
+        assert!((middle - expected).length() < 1e-4);
+        assert!((line.direction.length() - settings.tile_size).abs() < 1e-4);
+    }
+
+    #[test]
+    fn routes_across_the_seam_join_neighbors() {
+        let settings = GridSettings {
+            radius: 4,
+            wrap: WrapMode::Cylinder,
+            ..Default::default()
+        };
+        let edge = settings
+            .edge_between(HexCoord::new(-4, 1), HexCoord::new(5, -3))
+            .unwrap();
+        let line = road_line(&settings, &Grid::default(), edge);
+        let step = settings.get_global_pos(HexCoord::DIRECTIONS[0]).length();
+        assert!((line.direction.length() - step).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+
+use super::{Grid, GridSettings, HexCoord};
+
+/// Whether an eye `eye_height` above the tile `from` can see the top of the tile `to`.
+///
+/// The view is blocked by any tile along the hex line between them whose
+/// elevation rises above the straight sight line. Tiles missing from the grid
+/// never block. On wrapping maps, the line goes the shortest way around.
+pub fn line_of_sight(
+    grid: &Grid,
+    settings: &GridSettings,
+    from: HexCoord,
+    to: HexCoord,
+    eye_height: f32,
+) -> bool {
+    let from = settings.wrap(from);
+    let line = from.line_to(settings.nearest_copy(from, to));
+    let n = line.len() - 1;
+    if n <= 1 {
+        return true;
+    }
+    let elevation = |coords: HexCoord| grid.elevation(settings.wrap(coords));
+    let eye = elevation(from) + eye_height;
+    let target = elevation(line[n]);
+    line[1..n].iter().enumerate().all(|(i, coords)| {
+        let t = (i + 1) as f32 / n as f32;
+        let sight = eye + (target - eye) * t;
+        elevation(*coords) <= sight + f32::EPSILON
+    })
+}
+
+/// Every tile of the grid within `radius` of `from` that an eye `eye_height`
+/// above it can see, `from` included, wrapped around the seams.
+pub fn field_of_view(
+    grid: &Grid,
+    settings: &GridSettings,
+    from: HexCoord,
+    radius: u32,
+    eye_height: f32,
+) -> HashSet<HexCoord> {
+    from.spiral(radius)
+        .into_iter()
+        .map(|coords| settings.wrap(coords))
+        .filter(|coords| grid.contains(*coords))
+        .filter(|coords| line_of_sight(grid, settings, from, *coords, eye_height))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::{tests::flat_grid, WrapMode};
+
+    #[test]
+    fn everything_is_visible_on_flat_ground() {
+        let settings = GridSettings {
+            radius: 6,
+            ..Default::default()
+        };
+        let grid = flat_grid(&settings);
+        let fov = field_of_view(&grid, &settings, HexCoord::ZERO, 4, 0.5);
+        assert_eq!(fov.len(), 1 + 3 * 4 * 5);
+    }
+
+    #[test]
+    fn hills_hide_what_is_behind_them() {
+        let settings = GridSettings {
+            radius: 6,
+            ..Default::default()
+        };
+        let mut grid = flat_grid(&settings);
+        let hill = HexCoord::new(2, 0);
+        grid.elevations.insert(hill, 2.);
+        let behind = HexCoord::new(4, 0);
+        assert!(line_of_sight(&grid, &settings, HexCoord::ZERO, hill, 0.5));
+        assert!(!line_of_sight(
+            &grid,
+            &settings,
+            HexCoord::ZERO,
+            behind,
+            0.5
+        ));
+        // Standing high enough, the eye sees over the hill.
+        grid.elevations.insert(HexCoord::ZERO, 5.);
+        assert!(line_of_sight(&grid, &settings, HexCoord::ZERO, behind, 0.5));
+        // A high target is seen over a lower obstacle.
+        grid.elevations.insert(HexCoord::ZERO, 0.);
+        grid.elevations.insert(behind, 4.);
+        assert!(line_of_sight(&grid, &settings, HexCoord::ZERO, behind, 0.5));
+
+        grid.elevations.insert(behind, 0.);
+        let fov = field_of_view(&grid, &settings, HexCoord::ZERO, 5, 0.5);
+        assert!(fov.contains(&hill));
+        assert!(!fov.contains(&behind));
+        assert!(fov.contains(&HexCoord::new(0, 4)));
+    }
+
+    #[test]
+    fn sight_goes_across_the_seam() {
+        let settings = GridSettings {
+            radius: 4,
+            wrap: WrapMode::Cylinder,
+            ..Default::default()
+        };
+        let mut grid = flat_grid(&settings);
+        // On the west edge, looking west onto the east edge.
+        let from = HexCoord::new(-4, 2);
+        let west = |steps| settings.wrap(from - HexCoord::new(steps, 0));
+        assert_ne!(west(1), from - HexCoord::new(1, 0));
+        let fov = field_of_view(&grid, &settings, from, 3, 0.5);
+        assert_eq!(fov.len(), 1 + 3 * 3 * 4);
+        assert!(fov.contains(&west(3)));
+        assert!(fov.iter().all(|coords| grid.contains(*coords)));
+
+        grid.elevations.insert(west(1), 2.);
+        assert!(line_of_sight(&grid, &settings, from, west(1), 0.5));
+        assert!(!line_of_sight(&grid, &settings, from, west(3), 0.5));
+        assert!(!field_of_view(&grid, &settings, from, 3, 0.5).contains(&west(3)));
+    }
+}
@@ -0,0 +1,179 @@
+use std::ops::{Add, Mul, Sub};
+
+use serde::{Deserialize, Serialize};
+
+use super::HexCoord;
+
+/// Column and row of a tile on a square grid.
+///
+/// `Grid` keys its tiles by [`HexCoord`]; square tiles are stored with `x` as
+/// `q` and `y` as `r`, through the `From` conversions below.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SquareCoord {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Which tiles count as adjacent on a square grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Connectivity {
+    /// Edge neighbors only, distances are Manhattan distances.
+    Four,
+    /// Edge and corner neighbors, distances are Chebyshev distances.
+    Eight,
+}
+
+impl SquareCoord {
+    pub const ZERO: Self = Self::new(0, 0);
+
+    /// Edge neighbors, in counter-clockwise order.
+    pub const DIRECTIONS_4: [Self; 4] = [
+        Self::new(1, 0),
+        Self::new(0, 1),
+        Self::new(-1, 0),
+        Self::new(0, -1),
+    ];
+
+    /// Edge and corner neighbors, in counter-clockwise order.
+    pub const DIRECTIONS_8: [Self; 8] = [
+        Self::new(1, 0),
+        Self::new(1, 1),
+        Self::new(0, 1),
+        Self::new(-1, 1),
+        Self::new(-1, 0),
+        Self::new(-1, -1),
+        Self::new(0, -1),
+        Self::new(1, -1),
+    ];
+
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn neighbors_4(self) -> [Self; 4] {
+        Self::DIRECTIONS_4.map(|dir| self + dir)
+    }
+
+    pub fn neighbors_8(self) -> [Self; 8] {
+        Self::DIRECTIONS_8.map(|dir| self + dir)
+    }
+
+    pub fn neighbors(self, connectivity: Connectivity) -> Vec<Self> {
+        match connectivity {
+            Connectivity::Four => self.neighbors_4().to_vec(),
+            Connectivity::Eight => self.neighbors_8().to_vec(),
+        }
+    }
+
+    pub fn manhattan(self, other: Self) -> i32 {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+
+    pub fn chebyshev(self, other: Self) -> i32 {
+        (self.x - other.x).abs().max((self.y - other.y).abs())
+    }
+
+    pub fn distance(self, other: Self, connectivity: Connectivity) -> i32 {
+        match connectivity {
+            Connectivity::Four => self.manhattan(other),
+            Connectivity::Eight => self.chebyshev(other),
+        }
+    }
+
+    /// Every tile on the border of the square of half-width `radius` around `self`.
+    pub fn ring(self, radius: u32) -> Vec<Self> {
+        if radius == 0 {
+            return vec![self];
+        }
+        let radius = radius as i32;
+        let mut tiles = Vec::with_capacity(8 * radius as usize);
+        let mut tile = self + Self::new(radius, -radius);
+        // Walk up the +X side first, so that the corner we started from comes last.
+        for dir in 1..5 {
+            let dir = Self::DIRECTIONS_4[dir % 4];
+            for _ in 0..2 * radius {
+                tile = tile + dir;
+                tiles.push(tile);
+            }
+        }
+        tiles
+    }
+
+    /// Every tile of the square of half-width `radius` around `self`, from the center outwards.
+    pub fn spiral(self, radius: u32) -> Vec<Self> {
+        let mut tiles = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1)) as usize);
+        for r in 0..=radius {
+            tiles.extend(self.ring(r));
+        }
+        tiles
+    }
+}
+
+impl Add for SquareCoord {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl Sub for SquareCoord {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl Mul<i32> for SquareCoord {
+    type Output = Self;
+
+    fn mul(self, rhs: i32) -> Self {
+        Self::new(self.x * rhs, self.y * rhs)
+    }
+}
+
+impl From<HexCoord> for SquareCoord {
+    fn from(coord: HexCoord) -> Self {
+        Self::new(coord.q, coord.r)
+    }
+}
+
+impl From<SquareCoord> for HexCoord {
+    fn from(coord: SquareCoord) -> Self {
+        Self::new(coord.x, coord.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn neighbors_match_their_metric() {
+        let center = SquareCoord::new(4, -7);
+        for n in center.neighbors_4() {
+            assert_eq!(center.manhattan(n), 1);
+        }
+        for n in center.neighbors_8() {
+            assert_eq!(center.chebyshev(n), 1);
+        }
+        assert_eq!(center.manhattan(SquareCoord::new(6, -4)), 5);
+        assert_eq!(center.chebyshev(SquareCoord::new(6, -4)), 3);
+    }
+
+    #[test]
+    fn spiral_covers_the_whole_square() {
+        let center = SquareCoord::new(-2, 3);
+        for radius in 0..6 {
+            let ring = center.ring(radius);
+            assert!(ring.iter().all(|t| center.chebyshev(*t) == radius as i32));
+            let spiral = center.spiral(radius);
+            let side = (2 * radius + 1) as usize;
+            assert_eq!(spiral.len(), side * side);
+            assert_eq!(spiral.iter().collect::<HashSet<_>>().len(), side * side);
+        }
+    }
+}
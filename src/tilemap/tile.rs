@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{ChunkMaterial, HexCoord};
+
+#[derive(Component)]
+pub struct Tile {
+    pub coords: HexCoord,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Terrain {
+    Grass,
+    Forest,
+    Water,
+    Mountain,
+    Sand,
+    Snow,
+    Desert,
+}
+
+/// Gameplay and display properties shared by every tile of a terrain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainProperties {
+    /// Movement points spent to enter a tile.
+    pub movement_cost: u32,
+    /// Bonus granted to a unit defending from a tile.
+    pub defense_bonus: i32,
+    pub passable: bool,
+    pub color: Color,
+}
+
+impl TerrainProperties {
+    /// Color of the terrain where it is explored but out of sight.
+    pub fn dimmed_color(&self) -> Color {
+        Color::rgb(
+            self.color.r() * 0.35,
+            self.color.g() * 0.35,
+            self.color.b() * 0.35,
+        )
+    }
+}
+
+impl Terrain {
+    pub const ALL: [Terrain; 7] = [
+        Terrain::Grass,
+        Terrain::Forest,
+        Terrain::Water,
+        Terrain::Mountain,
+        Terrain::Sand,
+        Terrain::Snow,
+        Terrain::Desert,
+    ];
+
+    pub fn properties(self) -> TerrainProperties {
+        match self {
+            Terrain::Grass => TerrainProperties {
+                movement_cost: 1,
+                defense_bonus: 0,
+                passable: true,
+                color: Color::rgb(0.35, 0.6, 0.25),
+            },
+            Terrain::Forest => TerrainProperties {
+                movement_cost: 2,
+                defense_bonus: 2,
+                passable: true,
+                color: Color::rgb(0.13, 0.37, 0.16),
+            },
+            Terrain::Water => TerrainProperties {
+                movement_cost: 1,
+                defense_bonus: 0,
+                passable: false,
+                color: Color::rgb(0.2, 0.4, 0.75),
+            },
+            Terrain::Mountain => TerrainProperties {
+                movement_cost: 3,
+                defense_bonus: 3,
+                passable: true,
+                color: Color::rgb(0.5, 0.47, 0.45),
+            },
+            Terrain::Sand => TerrainProperties {
+                movement_cost: 2,
+                defense_bonus: 0,
+                passable: true,
+                color: Color::rgb(0.86, 0.8, 0.55),
+            },
+            Terrain::Snow => TerrainProperties {
+                movement_cost: 2,
+                defense_bonus: 0,
+                passable: true,
+                color: Color::rgb(0.92, 0.94, 0.96),
+            },
+            Terrain::Desert => TerrainProperties {
+                movement_cost: 1,
+                defense_bonus: 0,
+                passable: true,
+                color: Color::rgb(0.85, 0.66, 0.4),
+            },
+        }
+    }
+}
+
+/// One material per terrain, shared by every tile of that terrain, along with
+/// a dimmed variant for tiles that are explored but out of sight.
+pub struct TerrainMaterials {
+    normal: HashMap<Terrain, Handle<StandardMaterial>>,
+    dimmed: HashMap<Terrain, Handle<StandardMaterial>>,
+    /// Material of merged chunks, which carry the terrain colors in their vertices.
+    chunk: Handle<ChunkMaterial>,
+}
+
+impl TerrainMaterials {
+    pub fn get(&self, terrain: Terrain) -> Handle<StandardMaterial> {
+        self.normal[&terrain].clone()
+    }
+
+    pub fn get_dimmed(&self, terrain: Terrain) -> Handle<StandardMaterial> {
+        self.dimmed[&terrain].clone()
+    }
+
+    pub fn chunk(&self) -> Handle<ChunkMaterial> {
+        self.chunk.clone()
+    }
+}
+
+impl FromWorld for TerrainMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world
+            .get_resource_mut::<Assets<StandardMaterial>>()
+            .expect("TileMapPlugin needs the StandardMaterial assets");
+        let mut normal = HashMap::new();
+        let mut dimmed = HashMap::new();
+        for terrain in Terrain::ALL {
+            let properties = terrain.properties();
+            normal.insert(
+                terrain,
+                materials.add(StandardMaterial {
+                    base_color: properties.color,
+                    ..Default::default()
+                }),
+            );
+            dimmed.insert(
+                terrain,
+                materials.add(StandardMaterial {
+                    base_color: properties.dimmed_color(),
+                    ..Default::default()
+                }),
+            );
+        }
+        let chunk = world
+            .get_resource_mut::<Assets<ChunkMaterial>>()
+            .expect("TileMapPlugin needs the ChunkMaterial assets")
+            .add(ChunkMaterial);
+        Self {
+            normal,
+            dimmed,
+            chunk,
+        }
+    }
+}